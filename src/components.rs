@@ -1,4 +1,11 @@
-use amethyst::ecs::{storage::NullStorage, Component};
+use amethyst::{
+    core::math::{Isometry3, Vector3},
+    ecs::{
+        storage::{DenseVecStorage, NullStorage},
+        Component, Entity,
+    },
+};
+use rand::{rngs::StdRng, SeedableRng};
 
 /// Camera Boom handle tag, used to identify the camera boom handle entity
 #[derive(Default)]
@@ -15,3 +22,362 @@ pub struct CharacterBody;
 impl Component for CharacterBody {
     type Storage = NullStorage<Self>;
 }
+
+/// Tag of the dynamic bodies that the character can't push.
+#[derive(Default)]
+pub struct NotPushable;
+
+impl Component for NotPushable {
+    type Storage = NullStorage<Self>;
+}
+
+/// Enables the render interpolation of the entity `Transform`.
+///
+/// Stores the last two physics poses, so the `Transform` can be blended between
+/// them using the time left over by the fixed physics step.
+pub struct TransformInterpolation {
+    pub previous: Isometry3<f32>,
+    pub current: Isometry3<f32>,
+    /// `false` until the first physics pose is captured.
+    pub initialized: bool,
+}
+
+impl Default for TransformInterpolation {
+    fn default() -> Self {
+        TransformInterpolation {
+            previous: Isometry3::identity(),
+            current: Isometry3::identity(),
+            initialized: false,
+        }
+    }
+}
+
+impl Component for TransformInterpolation {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Mass of the rigid body, copied from its `RigidBodyDesc` since the servers
+/// don't expose it.
+#[derive(Debug, Clone, Copy)]
+pub struct Mass(pub f32);
+
+impl Component for Mass {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Shape of the bodies created by a `Spawner`.
+#[derive(Debug, Clone, Copy)]
+pub enum SpawnShape {
+    Cube { half_extents: Vector3<f32> },
+    Sphere { radius: f32 },
+}
+
+/// Spawns a dynamic body each `interval` seconds, at a random position inside
+/// the volume centered on the spawner `Transform`.
+pub struct Spawner {
+    /// Seconds between two spawns; never shorter than `MIN_SPAWN_INTERVAL`.
+    pub interval: f32,
+    /// Half extents of the spawn volume.
+    pub half_extents: Vector3<f32>,
+    pub shape: SpawnShape,
+    pub initial_velocity: Vector3<f32>,
+    /// Once reached, the spawner waits until some of its bodies are deleted.
+    pub max_alive: usize,
+    /// Seconds the spawned bodies live, if any.
+    pub lifetime: Option<f32>,
+    /// When `true` the released bodies are kept disabled, and reused by the
+    /// next spawns instead of creating new ones.
+    pub pooling: bool,
+    pub(crate) time_bank: f32,
+    pub(crate) rng: StdRng,
+    pub(crate) alive: Vec<Entity>,
+    /// Released pooled entities, ready to be reused.
+    pub(crate) free: Vec<Entity>,
+}
+
+/// Shortest interval between two spawns.
+pub const MIN_SPAWN_INTERVAL: f32 = 0.01;
+
+impl Spawner {
+    /// The interval is clamped to `MIN_SPAWN_INTERVAL`.
+    pub fn new(interval: f32, shape: SpawnShape) -> Self {
+        Spawner {
+            interval: interval.max(MIN_SPAWN_INTERVAL),
+            half_extents: Vector3::zeros(),
+            shape,
+            initial_velocity: Vector3::zeros(),
+            max_alive: usize::max_value(),
+            lifetime: None,
+            pooling: false,
+            time_bank: 0.0,
+            rng: StdRng::from_entropy(),
+            alive: Vec::new(),
+            free: Vec::new(),
+        }
+    }
+
+    pub fn with_volume(mut self, half_extents: Vector3<f32>) -> Self {
+        self.half_extents = half_extents;
+        self
+    }
+
+    pub fn with_initial_velocity(mut self, velocity: Vector3<f32>) -> Self {
+        self.initial_velocity = velocity;
+        self
+    }
+
+    pub fn with_max_alive(mut self, max_alive: usize) -> Self {
+        self.max_alive = max_alive;
+        self
+    }
+
+    pub fn with_lifetime(mut self, seconds: f32) -> Self {
+        self.lifetime = Some(seconds);
+        self
+    }
+
+    pub fn with_pooling(mut self) -> Self {
+        self.pooling = true;
+        self
+    }
+
+    /// Makes the spawn sequence reproducible.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+}
+
+impl Component for Spawner {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The entity is deleted once its lifetime expires.
+pub struct Lifetime {
+    /// Remaining seconds.
+    pub remaining: f32,
+}
+
+impl Lifetime {
+    pub fn new(seconds: f32) -> Self {
+        Lifetime { remaining: seconds }
+    }
+}
+
+impl Component for Lifetime {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Marks an entity owned by the pool of a `Spawner`.
+///
+/// When released the entity is not deleted: its body is disabled and it's
+/// hidden, until the spawner enables it again at a new position.
+pub struct Pooled {
+    /// The spawner entity that owns this entity.
+    pub spawner: Entity,
+    /// `false` while the entity sits in the pool.
+    pub active: bool,
+}
+
+impl Pooled {
+    pub fn new(spawner: Entity) -> Self {
+        Pooled {
+            spawner,
+            active: true,
+        }
+    }
+}
+
+impl Component for Pooled {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Marks the entity as a trigger: its physics area reports the bodies that
+/// enter and exit it as `TriggerEntered` and `TriggerExited` events.
+#[derive(Debug, Clone, Default)]
+pub struct TriggerVolume {
+    /// Used to identify the trigger in the logs.
+    pub name: String,
+}
+
+impl TriggerVolume {
+    pub fn new(name: impl Into<String>) -> Self {
+        TriggerVolume { name: name.into() }
+    }
+}
+
+impl Component for TriggerVolume {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// Where the character is teleported when it falls out of the level.
+#[derive(Default)]
+pub struct RespawnPoint;
+
+impl Component for RespawnPoint {
+    type Storage = NullStorage<Self>;
+}
+
+/// Tag of the `TriggerVolume`s that respawn the character entering them.
+#[derive(Default)]
+pub struct KillZone;
+
+impl Component for KillZone {
+    type Storage = NullStorage<Self>;
+}
+
+/// Joint between this entity body and the `other` entity body.
+///
+/// It's created by the `JointSystem` once both bodies are created, and removing
+/// the component removes the joint.
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub other: Entity,
+    pub kind: JointKind,
+    /// Joint frame, relative to this entity.
+    pub anchor: Isometry3<f32>,
+    /// Force, in newtons, over which the joint breaks; `None` is unbreakable.
+    pub break_force: Option<f32>,
+}
+
+impl Joint {
+    pub fn new(other: Entity, kind: JointKind) -> Self {
+        Joint {
+            other,
+            kind,
+            anchor: Isometry3::identity(),
+            break_force: None,
+        }
+    }
+
+    pub fn with_anchor(mut self, anchor: Isometry3<f32>) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    pub fn with_break_force(mut self, break_force: f32) -> Self {
+        self.break_force = Some(break_force);
+        self
+    }
+}
+
+impl Component for Joint {
+    type Storage = DenseVecStorage<Self>;
+}
+
+/// The axes are expressed in the joint frame.
+#[derive(Debug, Clone, Copy)]
+pub enum JointKind {
+    /// Welds the two bodies together.
+    Fixed,
+    /// Rotation around a single axis; the limits are angles in radians.
+    Hinge {
+        axis: Vector3<f32>,
+        limits: Option<(f32, f32)>,
+        motor: Option<JointMotor>,
+    },
+    /// Free rotation around the anchor; the limit is the max angle, in radians,
+    /// between the Y axes of the two joint frames.
+    Ball { swing_limit: Option<f32> },
+    /// Translation along a single axis; the limits are distances.
+    Prismatic {
+        axis: Vector3<f32>,
+        limits: Option<(f32, f32)>,
+        motor: Option<JointMotor>,
+    },
+}
+
+/// Drives the free axis of a joint toward a target velocity.
+#[derive(Debug, Clone, Copy)]
+pub struct JointMotor {
+    /// Angular velocity for the hinges, linear velocity for the prismatic joints.
+    pub target_velocity: f32,
+    pub max_force: f32,
+}
+
+/// Set of bodies, joined by joints, built by `ragdoll::build_ragdoll`.
+///
+/// While inactive the bodies are kinematic and follow the animated nodes, or
+/// the rest pose; once activated they are simulated, for example when knocked
+/// out.
+#[derive(Debug, Clone, Default)]
+pub struct Ragdoll {
+    pub bones: Vec<RagdollBone>,
+    pub active: bool,
+}
+
+impl Component for Ragdoll {
+    type Storage = DenseVecStorage<Self>;
+}
+
+#[derive(Debug, Clone)]
+pub struct RagdollBone {
+    /// The entity with the bone body.
+    pub entity: Entity,
+    /// Animated node the bone follows, found by its `Named` component when the
+    /// ragdoll is built.
+    pub node: Option<Entity>,
+    /// Bone pose, relative to the ragdoll entity, used when the node is missing.
+    pub rest: Isometry3<f32>,
+    /// Bone pose relative to the node, measured the first time it's found.
+    pub node_offset: Option<Isometry3<f32>>,
+}
+
+/// Volume that applies a continuous force to the dynamic bodies inside it,
+/// character included.
+///
+/// The volume is centered on the entity `Transform`, and is not rotated.
+#[derive(Debug, Clone, Copy)]
+pub struct ForceField {
+    pub volume: ForceVolume,
+    pub kind: ForceFieldKind,
+}
+
+impl ForceField {
+    pub fn new(volume: ForceVolume, kind: ForceFieldKind) -> Self {
+        ForceField { volume, kind }
+    }
+}
+
+impl Component for ForceField {
+    type Storage = DenseVecStorage<Self>;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ForceVolume {
+    Box { half_extents: Vector3<f32> },
+    Sphere { radius: f32 },
+}
+
+impl ForceVolume {
+    /// Distance from the center to the border, in the given direction.
+    pub fn extent(&self, direction: &Vector3<f32>) -> f32 {
+        match self {
+            ForceVolume::Sphere { radius } => *radius,
+            ForceVolume::Box { half_extents } => {
+                let direction = direction.normalize();
+                [0, 1, 2]
+                    .iter()
+                    .filter(|i| direction[**i].abs() > std::f32::EPSILON)
+                    .map(|i| half_extents[*i] / direction[*i].abs())
+                    .fold(std::f32::MAX, f32::min)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ForceFieldKind {
+    /// Constant force, in newtons.
+    Wind { force: Vector3<f32> },
+    /// Acceleration toward the volume center, negative to push away; it falls
+    /// off linearly to zero at the volume border.
+    GravityWell { strength: f32 },
+    /// Water filling the volume up to its top.
+    Buoyancy {
+        /// Fluid density relative to the bodies: over 1 the bodies float.
+        density: f32,
+        /// Drag applied to the submerged part, in 1/s.
+        drag: f32,
+    },
+}
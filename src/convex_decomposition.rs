@@ -0,0 +1,130 @@
+use std::path::{Path, PathBuf};
+
+use amethyst::{
+    config::Config,
+    core::math::{Isometry3, Point3},
+};
+use amethyst_physics::prelude::*;
+use ncollide3d::{
+    procedural::{IndexBuffer, TriMesh},
+    transformation,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::mesh_collider::MeshGeometry;
+
+/// Default concavity error accepted for each convex piece.
+pub const DEFAULT_ERROR: f32 = 0.03;
+/// Default minimum number of convex pieces.
+pub const DEFAULT_MIN_COMPONENTS: usize = 1;
+
+/// Convex pieces of a concave mesh.
+///
+/// It's stored as RON next to the mesh file, so the decomposition runs only
+/// the first time the mesh is loaded.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ConvexDecomposition {
+    /// Parameters used to compute this decomposition.
+    pub error: f32,
+    pub min_components: usize,
+    /// The points of each convex piece.
+    pub pieces: Vec<Vec<[f32; 3]>>,
+}
+
+impl ConvexDecomposition {
+    /// Runs the HACD decomposition on the mesh triangles.
+    pub fn compute(geometry: &MeshGeometry, error: f32, min_components: usize) -> Self {
+        let mut mesh = TriMesh::new(
+            geometry.points.clone(),
+            None,
+            None,
+            Some(IndexBuffer::Unified(
+                geometry
+                    .indices
+                    .iter()
+                    .map(|t| Point3::new(t.x as u32, t.y as u32, t.z as u32))
+                    .collect(),
+            )),
+        );
+        mesh.recompute_normals();
+
+        let (pieces, _) = transformation::hacd(mesh, error, min_components);
+
+        ConvexDecomposition {
+            error,
+            min_components,
+            pieces: pieces
+                .iter()
+                .map(|piece| piece.coords.iter().map(|p| [p.x, p.y, p.z]).collect())
+                .collect(),
+        }
+    }
+
+    /// Returns the cached decomposition of the mesh file, or computes it from
+    /// `geometry`, the triangles already loaded from that file, and caches it
+    /// when the cache is missing, older than the mesh, or made with other
+    /// parameters.
+    pub fn load_or_compute(
+        mesh_path: impl AsRef<Path>,
+        geometry: &MeshGeometry,
+        error: f32,
+        min_components: usize,
+    ) -> Self {
+        let mesh_path = mesh_path.as_ref();
+        let cache_path = cache_path(mesh_path);
+
+        if is_cache_fresh(mesh_path, &cache_path) {
+            if let Ok(cached) = ConvexDecomposition::load(&cache_path) {
+                if cached.error == error && cached.min_components == min_components {
+                    return cached;
+                }
+            }
+        }
+
+        let decomposition = ConvexDecomposition::compute(geometry, error, min_components);
+        if let Err(e) = decomposition.write(&cache_path) {
+            log::warn!(
+                "Failed to cache the convex decomposition {}: {}",
+                cache_path.display(),
+                e
+            );
+        }
+        decomposition
+    }
+
+    /// Compound of the convex pieces, scaled by `scale`.
+    pub fn shape_desc(&self, scale: f32) -> ShapeDesc<f32> {
+        ShapeDesc::Compound {
+            shapes: self
+                .pieces
+                .iter()
+                .map(|piece| {
+                    (
+                        Isometry3::identity(),
+                        ShapeDesc::Convex {
+                            points: piece
+                                .iter()
+                                .map(|p| Point3::new(p[0], p[1], p[2]) * scale)
+                                .collect(),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The cache of `mesh/Monster.gltf` is `mesh/Monster.gltf.convex.ron`.
+fn cache_path(mesh_path: &Path) -> PathBuf {
+    let mut file_name = mesh_path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".convex.ron");
+    mesh_path.with_file_name(file_name)
+}
+
+fn is_cache_fresh(mesh_path: &Path, cache_path: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified());
+    match (modified(mesh_path), modified(cache_path)) {
+        (Ok(mesh_time), Ok(cache_time)) => cache_time >= mesh_time,
+        _ => false,
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::{HashMap, HashSet};
+
+use amethyst::{
+    core::math::{Isometry3, Point3, Vector3},
+    ecs::prelude::*,
+    input::{InputEvent, StringBindings},
+    renderer::{debug_drawing::DebugLines, palette::Srgba},
+    shrev::EventChannel,
+};
+use amethyst_physics::prelude::*;
+use ncollide3d::{procedural::IndexBuffer, transformation};
+
+use crate::resources::ShapeRegistry;
+
+/// Segments used to draw the circles.
+const CIRCLE_SEGMENTS: usize = 16;
+/// Under this speed the body is drawn as resting.
+const RESTING_SPEED: f32 = 0.05;
+/// Length of the contact normals.
+const NORMAL_LENGTH: f32 = 0.5;
+
+/// Segment of an outline, in the shape space.
+type Segment = (Point3<f32>, Point3<f32>);
+
+/// Toggleable overlay that draws, with debug lines, the outline of every
+/// collider and area, the body centers and the reported contacts.
+///
+/// The resting bodies are drawn with a different color. Since the servers don't
+/// expose the sleeping state, a body is considered resting when its velocity
+/// is almost zero.
+/// Only the shapes created through the `ShapeRegistry` can be drawn.
+#[derive(Debug, Default)]
+pub struct PhysicsDebugDrawSystem {
+    input_event_reader: Option<ReaderId<InputEvent<StringBindings>>>,
+    enabled: bool,
+    contact_events: Vec<ContactEvent<f32>>,
+    /// Outline of each shape, built the first time the shape is drawn.
+    outlines: HashMap<PhysicsShapeTag, Vec<Segment>>,
+    /// Shapes drawn by the last run; the outlines of the others are dropped.
+    drawn: HashSet<PhysicsShapeTag>,
+}
+
+impl PhysicsDebugDrawSystem {
+    fn draw_outline(
+        &mut self,
+        debug_lines: &mut DebugLines,
+        shape_registry: &ShapeRegistry,
+        shape: PhysicsShapeTag,
+        pose: &Isometry3<f32>,
+        color: Srgba,
+    ) {
+        self.drawn.insert(shape);
+        let outline = self.outlines.entry(shape).or_insert_with(|| {
+            let mut outline = Vec::new();
+            if let Some(desc) = shape_registry.desc(shape) {
+                shape_outline(desc, &Isometry3::identity(), &mut outline);
+            }
+            outline
+        });
+        for (a, b) in outline.iter() {
+            debug_lines.draw_line(pose * a, pose * b, color);
+        }
+    }
+}
+
+impl<'s> System<'s> for PhysicsDebugDrawSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        Read<'s, ShapeRegistry>,
+        ReadExpect<'s, EventChannel<InputEvent<StringBindings>>>,
+        Write<'s, DebugLines>,
+        ReadStorage<'s, PhysicsHandle<PhysicsShapeTag>>,
+        ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
+        ReadStorage<'s, PhysicsHandle<PhysicsAreaTag>>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            physics_world,
+            shape_registry,
+            input_event_channel,
+            mut debug_lines,
+            shapes,
+            bodies,
+            areas,
+        ): Self::SystemData,
+    ) {
+        for e in input_event_channel.read(self.input_event_reader.as_mut().unwrap()) {
+            if let InputEvent::ActionPressed(action) = e {
+                if action == "ToggleDebugDraw" {
+                    self.enabled = !self.enabled;
+                }
+            }
+        }
+
+        if !self.enabled {
+            self.outlines.clear();
+            return;
+        }
+        self.drawn.clear();
+
+        let rb_server = physics_world.rigid_body_server();
+        for (shape, body) in (&shapes, &bodies).join() {
+            let pose = rb_server.transform(body.get());
+
+            let is_resting = rb_server.linear_velocity(body.get()).norm() < RESTING_SPEED
+                && rb_server.angular_velocity(body.get()).norm() < RESTING_SPEED;
+            let color = match rb_server.mode(body.get()) {
+                BodyMode::Static => Srgba::new(0.5, 0.5, 0.5, 1.0),
+                _ if is_resting => Srgba::new(0.2, 0.4, 1.0, 1.0),
+                _ => Srgba::new(0.0, 1.0, 0.0, 1.0),
+            };
+            self.draw_outline(&mut debug_lines, &shape_registry, shape.get(), &pose, color);
+
+            // Body center
+            draw_cross(
+                &mut debug_lines,
+                &Point3::from(pose.translation.vector),
+                0.15,
+                Srgba::new(1.0, 1.0, 0.0, 1.0),
+            );
+
+            // Contacts
+            rb_server.contact_events(body.get(), &mut self.contact_events);
+            for contact in &self.contact_events {
+                draw_cross(
+                    &mut debug_lines,
+                    &contact.location,
+                    0.05,
+                    Srgba::new(1.0, 0.0, 0.0, 1.0),
+                );
+                debug_lines.draw_line(
+                    contact.location,
+                    contact.location + contact.normal.into_inner() * NORMAL_LENGTH,
+                    Srgba::new(1.0, 0.5, 0.0, 1.0),
+                );
+            }
+        }
+
+        let area_server = physics_world.area_server();
+        for (shape, area) in (&shapes, &areas).join() {
+            let pose = area_server.transform(area.get());
+            self.draw_outline(
+                &mut debug_lines,
+                &shape_registry,
+                shape.get(),
+                &pose,
+                Srgba::new(1.0, 0.0, 1.0, 1.0),
+            );
+        }
+
+        // Forget the outlines of the shapes not used anymore.
+        let drawn = &self.drawn;
+        self.outlines.retain(|tag, _| drawn.contains(tag));
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        let mut ie = world.fetch_mut::<EventChannel<InputEvent<StringBindings>>>();
+        self.input_event_reader = Some(ie.register_reader());
+    }
+}
+
+/// Appends the wireframe of the shape, moved by `pose`, to `outline`.
+fn shape_outline(desc: &ShapeDesc<f32>, pose: &Isometry3<f32>, outline: &mut Vec<Segment>) {
+    match desc {
+        ShapeDesc::Sphere { radius } => {
+            circle_outline(pose, &Vector3::x(), &Vector3::y(), *radius, outline);
+            circle_outline(pose, &Vector3::y(), &Vector3::z(), *radius, outline);
+            circle_outline(pose, &Vector3::z(), &Vector3::x(), *radius, outline);
+        }
+        ShapeDesc::Cube { half_extents } => {
+            let corner = |x: f32, y: f32, z: f32| {
+                pose * Point3::new(x * half_extents.x, y * half_extents.y, z * half_extents.z)
+            };
+            for &(a, b) in &[
+                ((-1.0, -1.0, -1.0), (1.0, -1.0, -1.0)),
+                ((-1.0, 1.0, -1.0), (1.0, 1.0, -1.0)),
+                ((-1.0, -1.0, 1.0), (1.0, -1.0, 1.0)),
+                ((-1.0, 1.0, 1.0), (1.0, 1.0, 1.0)),
+                ((-1.0, -1.0, -1.0), (-1.0, 1.0, -1.0)),
+                ((1.0, -1.0, -1.0), (1.0, 1.0, -1.0)),
+                ((-1.0, -1.0, 1.0), (-1.0, 1.0, 1.0)),
+                ((1.0, -1.0, 1.0), (1.0, 1.0, 1.0)),
+                ((-1.0, -1.0, -1.0), (-1.0, -1.0, 1.0)),
+                ((1.0, -1.0, -1.0), (1.0, -1.0, 1.0)),
+                ((-1.0, 1.0, -1.0), (-1.0, 1.0, 1.0)),
+                ((1.0, 1.0, -1.0), (1.0, 1.0, 1.0)),
+            ] {
+                outline.push((corner(a.0, a.1, a.2), corner(b.0, b.1, b.2)));
+            }
+        }
+        ShapeDesc::Capsule {
+            half_height,
+            radius,
+        } => {
+            let top = pose * Isometry3::translation(0.0, *half_height, 0.0);
+            let bottom = pose * Isometry3::translation(0.0, -*half_height, 0.0);
+            for cap in &[top, bottom] {
+                circle_outline(cap, &Vector3::z(), &Vector3::x(), *radius, outline);
+                circle_outline(cap, &Vector3::x(), &Vector3::y(), *radius, outline);
+                circle_outline(cap, &Vector3::y(), &Vector3::z(), *radius, outline);
+            }
+            side_outline(&top, &bottom, *radius, outline);
+        }
+        ShapeDesc::Cylinder {
+            half_height,
+            radius,
+        } => {
+            let top = pose * Isometry3::translation(0.0, *half_height, 0.0);
+            let bottom = pose * Isometry3::translation(0.0, -*half_height, 0.0);
+            circle_outline(&top, &Vector3::z(), &Vector3::x(), *radius, outline);
+            circle_outline(&bottom, &Vector3::z(), &Vector3::x(), *radius, outline);
+            side_outline(&top, &bottom, *radius, outline);
+        }
+        ShapeDesc::Convex { points } => {
+            if points.len() < 4 {
+                return;
+            }
+            let hull = transformation::convex_hull(points);
+            let triangles: Vec<[usize; 3]> = match &hull.indices {
+                IndexBuffer::Unified(indices) => indices
+                    .iter()
+                    .map(|t| [t.x as usize, t.y as usize, t.z as usize])
+                    .collect(),
+                IndexBuffer::Split(indices) => indices
+                    .iter()
+                    .map(|t| [t.x.x as usize, t.y.x as usize, t.z.x as usize])
+                    .collect(),
+            };
+            triangles_outline(pose, &hull.coords, &triangles, outline);
+        }
+        ShapeDesc::TriMesh { points, indices } => {
+            let triangles: Vec<[usize; 3]> = indices.iter().map(|t| [t.x, t.y, t.z]).collect();
+            triangles_outline(pose, points, &triangles, outline);
+        }
+        ShapeDesc::Compound { shapes } => {
+            for (offset, shape) in shapes {
+                shape_outline(shape, &(pose * offset), outline);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Appends the circle that lies on the plane of the two local axes.
+fn circle_outline(
+    pose: &Isometry3<f32>,
+    axis_a: &Vector3<f32>,
+    axis_b: &Vector3<f32>,
+    radius: f32,
+    outline: &mut Vec<Segment>,
+) {
+    let point = |i: usize| {
+        let angle = i as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::PI * 2.0;
+        pose * Point3::from((axis_a * angle.cos() + axis_b * angle.sin()) * radius)
+    };
+    for i in 0..CIRCLE_SEGMENTS {
+        outline.push((point(i), point(i + 1)));
+    }
+}
+
+/// Appends four lines that join the top and the bottom circles of a round shape.
+fn side_outline(
+    top: &Isometry3<f32>,
+    bottom: &Isometry3<f32>,
+    radius: f32,
+    outline: &mut Vec<Segment>,
+) {
+    for offset in &[
+        Vector3::x() * radius,
+        Vector3::x() * -radius,
+        Vector3::z() * radius,
+        Vector3::z() * -radius,
+    ] {
+        outline.push((top * Point3::from(*offset), bottom * Point3::from(*offset)));
+    }
+}
+
+/// Appends the edges of the triangles, each shared edge only once.
+fn triangles_outline(
+    pose: &Isometry3<f32>,
+    points: &[Point3<f32>],
+    triangles: &[[usize; 3]],
+    outline: &mut Vec<Segment>,
+) {
+    let mut edges = HashSet::new();
+    for t in triangles {
+        for &(a, b) in &[(t[0], t[1]), (t[1], t[2]), (t[2], t[0])] {
+            if edges.insert((a.min(b), a.max(b))) {
+                outline.push((pose * points[a], pose * points[b]));
+            }
+        }
+    }
+}
+
+fn draw_cross(debug_lines: &mut DebugLines, center: &Point3<f32>, size: f32, color: Srgba) {
+    for axis in &[Vector3::x(), Vector3::y(), Vector3::z()] {
+        debug_lines.draw_line(center - axis * size, center + axis * size, color);
+    }
+}
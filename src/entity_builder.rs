@@ -0,0 +1,305 @@
+use amethyst::{
+    assets::Handle,
+    core::{
+        math::{UnitQuaternion, Vector3},
+        Parent, Transform,
+    },
+    ecs::{prelude::*, world::EntitiesRes},
+    renderer::{mtl, palette::LinSrgba, types, visibility::BoundingSphere},
+};
+use amethyst_physics::prelude::*;
+
+use crate::{
+    components::{Mass, TransformInterpolation},
+    resources::ShapeRegistry,
+    visual_utils,
+};
+
+/// Visual parameters of the built entity.
+#[derive(Debug, Clone)]
+struct VisualParams {
+    color: LinSrgba,
+    metallic: f32,
+    roughness: f32,
+    /// When `None` the mesh is generated from the shape.
+    mesh: Option<Handle<types::Mesh>>,
+}
+
+impl Default for VisualParams {
+    fn default() -> Self {
+        VisualParams {
+            color: LinSrgba::new(1.0, 1.0, 1.0, 1.0),
+            metallic: 0.0,
+            roughness: 1.0,
+            mesh: None,
+        }
+    }
+}
+
+/// Fluent builder of an entity with shape, rigid body and visual.
+///
+/// ```ignore
+/// PhysicsEntityBuilder::new()
+///     .cube(Vector3::new(1.0, 1.0, 1.0))
+///     .dynamic()
+///     .mass(2.0)
+///     .color(LinSrgba::new(1.0, 0.0, 0.0, 1.0))
+///     .at(Vector3::new(0.0, 6.0, 0.0))
+///     .build(world);
+/// ```
+///
+/// From a `System` use `build_lazy`, which reserves the entity right away and
+/// creates the physics and visual assets at the end of the frame.
+#[derive(Debug)]
+pub struct PhysicsEntityBuilder {
+    shape: ShapeDesc<f32>,
+    rb_desc: RigidBodyDesc<f32>,
+    position: Vector3<f32>,
+    rotation: UnitQuaternion<f32>,
+    velocity: Vector3<f32>,
+    visual: Option<VisualParams>,
+    /// When `None` only the dynamic bodies are interpolated.
+    interpolate: Option<bool>,
+}
+
+impl Default for PhysicsEntityBuilder {
+    fn default() -> Self {
+        PhysicsEntityBuilder {
+            shape: ShapeDesc::Cube {
+                half_extents: Vector3::new(0.5, 0.5, 0.5),
+            },
+            rb_desc: RigidBodyDesc::default(),
+            position: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
+            velocity: Vector3::zeros(),
+            visual: Some(VisualParams::default()),
+            interpolate: None,
+        }
+    }
+}
+
+impl PhysicsEntityBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn shape(mut self, shape: ShapeDesc<f32>) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    pub fn cube(self, half_extents: Vector3<f32>) -> Self {
+        self.shape(ShapeDesc::Cube { half_extents })
+    }
+
+    pub fn sphere(self, radius: f32) -> Self {
+        self.shape(ShapeDesc::Sphere { radius })
+    }
+
+    pub fn capsule(self, half_height: f32, radius: f32) -> Self {
+        self.shape(ShapeDesc::Capsule {
+            half_height,
+            radius,
+        })
+    }
+
+    /// Replaces the whole rigid body description.
+    pub fn rigid_body(mut self, rb_desc: RigidBodyDesc<f32>) -> Self {
+        self.rb_desc = rb_desc;
+        self
+    }
+
+    pub fn mode(mut self, mode: BodyMode) -> Self {
+        self.rb_desc.mode = mode;
+        self
+    }
+
+    pub fn dynamic(self) -> Self {
+        self.mode(BodyMode::Dynamic)
+    }
+
+    pub fn static_body(self) -> Self {
+        self.mode(BodyMode::Static)
+    }
+
+    pub fn mass(mut self, mass: f32) -> Self {
+        self.rb_desc.mass = mass;
+        self
+    }
+
+    pub fn friction(mut self, friction: f32) -> Self {
+        self.rb_desc.friction = friction;
+        self
+    }
+
+    pub fn bounciness(mut self, bounciness: f32) -> Self {
+        self.rb_desc.bounciness = bounciness;
+        self
+    }
+
+    pub fn at(mut self, position: Vector3<f32>) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn rotated(mut self, rotation: UnitQuaternion<f32>) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn velocity(mut self, velocity: Vector3<f32>) -> Self {
+        self.velocity = velocity;
+        self
+    }
+
+    pub fn color(mut self, color: LinSrgba) -> Self {
+        self.visual_mut().color = color;
+        self
+    }
+
+    pub fn metallic_roughness(mut self, metallic: f32, roughness: f32) -> Self {
+        let visual = self.visual_mut();
+        visual.metallic = metallic;
+        visual.roughness = roughness;
+        self
+    }
+
+    /// Uses this mesh instead of generating it from the shape.
+    pub fn mesh(mut self, mesh: Handle<types::Mesh>) -> Self {
+        self.visual_mut().mesh = Some(mesh);
+        self
+    }
+
+    /// The entity has no visual.
+    pub fn invisible(mut self) -> Self {
+        self.visual = None;
+        self
+    }
+
+    pub fn interpolate(mut self, interpolate: bool) -> Self {
+        self.interpolate = Some(interpolate);
+        self
+    }
+
+    fn visual_mut(&mut self) -> &mut VisualParams {
+        self.visual.get_or_insert_with(VisualParams::default)
+    }
+
+    pub fn build(self, world: &mut World) -> Entity {
+        let entity = world.create_entity().build();
+        self.build_into(world, entity);
+        entity
+    }
+
+    /// Adds the components to an already created entity.
+    pub fn build_into(self, world: &mut World, entity: Entity) {
+        let shape = {
+            let physics_world = world.fetch::<PhysicsWorld<f32>>();
+            world
+                .fetch_mut::<ShapeRegistry>()
+                .get_or_create(&physics_world, &self.shape)
+        };
+
+        let rb = {
+            let physics_world = world.fetch::<PhysicsWorld<f32>>();
+            let rb = physics_world.rigid_body_server().create(&self.rb_desc);
+            if self.velocity != Vector3::zeros() {
+                physics_world
+                    .rigid_body_server()
+                    .set_linear_velocity(rb.get(), &self.velocity);
+            }
+            rb
+        };
+
+        let interpolate = self
+            .interpolate
+            .unwrap_or(self.rb_desc.mode == BodyMode::Dynamic);
+
+        insert(
+            world,
+            entity,
+            Transform::new(
+                self.position.into(),
+                self.rotation,
+                Vector3::new(1.0, 1.0, 1.0),
+            ),
+        );
+        insert(world, entity, shape);
+        insert(world, entity, rb);
+        insert(world, entity, Mass(self.rb_desc.mass));
+        if interpolate {
+            insert(world, entity, TransformInterpolation::default());
+        }
+
+        if let Some(visual) = self.visual {
+            let mat = visual_utils::shared_material(
+                world,
+                visual.color,
+                visual.metallic,
+                visual.roughness,
+            );
+            match visual.mesh {
+                Some(mesh) => {
+                    insert(world, entity, mesh);
+                    insert(world, entity, mat);
+                    insert(
+                        world,
+                        entity,
+                        BoundingSphere::origin(visual_utils::bounding_radius(&self.shape)),
+                    );
+                }
+                None => add_shape_visual(world, entity, &self.shape, &mat),
+            }
+        }
+    }
+
+    /// Reserves the entity now, and builds it at the end of the frame.
+    pub fn build_lazy(self, entities: &EntitiesRes, lazy: &LazyUpdate) -> Entity {
+        let entity = entities.create();
+        lazy.exec_mut(move |world| self.build_into(world, entity));
+        entity
+    }
+}
+
+/// Adds the mesh generated from the shape to the entity.
+///
+/// Each part of a compound is drawn by a child entity, placed at the part offset.
+fn add_shape_visual(
+    world: &mut World,
+    entity: Entity,
+    shape: &ShapeDesc<f32>,
+    mat: &Handle<mtl::Material>,
+) {
+    if let ShapeDesc::Compound { shapes } = shape {
+        for (offset, part) in shapes {
+            let child = world
+                .create_entity()
+                .with(Transform::new(
+                    offset.translation,
+                    offset.rotation,
+                    Vector3::new(1.0, 1.0, 1.0),
+                ))
+                .with(Parent { entity })
+                .build();
+            add_shape_visual(world, child, part, mat);
+        }
+        return;
+    }
+
+    if let Some(mesh) = visual_utils::mesh_from_shape_desc(world, shape) {
+        insert(world, entity, mesh);
+        insert(world, entity, mat.clone());
+        insert(
+            world,
+            entity,
+            BoundingSphere::origin(visual_utils::bounding_radius(shape)),
+        );
+    }
+}
+
+fn insert<C: Component>(world: &World, entity: Entity, component: C) {
+    world
+        .write_storage()
+        .insert(entity, component)
+        .expect("The entity is alive");
+}
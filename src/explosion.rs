@@ -0,0 +1,123 @@
+use amethyst::{
+    core::math::{Point3, Vector3},
+    ecs::prelude::*,
+    input::{InputEvent, StringBindings},
+    shrev::EventChannel,
+};
+use amethyst_physics::prelude::*;
+
+use crate::resources::MousePicking;
+
+/// Radius of the explosions triggered with the `Explode` action.
+const DEBUG_EXPLOSION_RADIUS: f32 = 6.0;
+/// Strength of the explosions triggered with the `Explode` action.
+const DEBUG_EXPLOSION_STRENGTH: f32 = 60.0;
+/// When nothing is picked, the debug explosion happens this far along the view ray.
+const DEBUG_EXPLOSION_DISTANCE: f32 = 8.0;
+
+/// Radial impulse, written on the `EventChannel<Explosion>`.
+#[derive(Debug, Clone, Copy)]
+pub struct Explosion {
+    pub center: Point3<f32>,
+    pub radius: f32,
+    /// Impulse given to a body in the center; it falls off linearly to zero
+    /// at the radius.
+    pub strength: f32,
+}
+
+/// Applies the `Explosion` events to the dynamic bodies in range.
+///
+/// The distance is measured from the body center, so a big body touched only
+/// on the edge of the radius is not pushed.
+/// The impulse doesn't depend on the body mass, like the push of a blast: a
+/// light body flies away, while a heavy crate barely moves.
+#[derive(Debug, Default)]
+pub struct ExplosionSystem {
+    explosion_reader: Option<ReaderId<Explosion>>,
+}
+
+impl<'s> System<'s> for ExplosionSystem {
+    type SystemData = (
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        Read<'s, EventChannel<Explosion>>,
+        ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
+    );
+
+    fn run(&mut self, (physics_world, explosions, rigid_body_tags): Self::SystemData) {
+        let rb_server = physics_world.rigid_body_server();
+        for explosion in explosions.read(self.explosion_reader.as_mut().unwrap()) {
+            for body in (&rigid_body_tags).join() {
+                if rb_server.mode(body.get()) != BodyMode::Dynamic {
+                    continue;
+                }
+
+                let offset =
+                    rb_server.transform(body.get()).translation.vector - explosion.center.coords;
+                let distance = offset.norm();
+                if distance > explosion.radius {
+                    continue;
+                }
+
+                // The bodies in the center are pushed up.
+                let direction = if distance > std::f32::EPSILON {
+                    offset / distance
+                } else {
+                    Vector3::y()
+                };
+                let falloff = 1.0 - distance / explosion.radius;
+                rb_server.apply_impulse(body.get(), &(direction * explosion.strength * falloff));
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.explosion_reader = Some(
+            world
+                .fetch_mut::<EventChannel<Explosion>>()
+                .register_reader(),
+        );
+    }
+}
+
+/// Triggers an explosion on the picked point with the `Explode` action, to
+/// stress test the piles of bodies.
+#[derive(Debug, Default)]
+pub struct ExplosionDebugSystem {
+    input_event_reader: Option<ReaderId<InputEvent<StringBindings>>>,
+}
+
+impl<'s> System<'s> for ExplosionDebugSystem {
+    type SystemData = (
+        ReadExpect<'s, EventChannel<InputEvent<StringBindings>>>,
+        Read<'s, MousePicking>,
+        Write<'s, EventChannel<Explosion>>,
+    );
+
+    fn run(&mut self, (input_event_channel, picking, mut explosions): Self::SystemData) {
+        for e in input_event_channel.read(self.input_event_reader.as_mut().unwrap()) {
+            if let InputEvent::ActionPressed(action) = e {
+                if action != "Explode" {
+                    continue;
+                }
+
+                let center = match (&picking.hit, &picking.ray) {
+                    (Some(hit), _) => hit.point,
+                    (None, Some(ray)) => ray.origin + ray.direction * DEBUG_EXPLOSION_DISTANCE,
+                    (None, None) => continue,
+                };
+                explosions.single_write(Explosion {
+                    center,
+                    radius: DEBUG_EXPLOSION_RADIUS,
+                    strength: DEBUG_EXPLOSION_STRENGTH,
+                });
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        let mut ie = world.fetch_mut::<EventChannel<InputEvent<StringBindings>>>();
+        self.input_event_reader = Some(ie.register_reader());
+    }
+}
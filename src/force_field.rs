@@ -0,0 +1,94 @@
+use amethyst::{
+    core::{math::Vector3, Transform},
+    ecs::prelude::*,
+};
+use amethyst_physics::prelude::*;
+
+use crate::{
+    components::{ForceField, ForceFieldKind, ForceVolume, Mass},
+    resources::ShapeRegistry,
+    visual_utils,
+};
+
+/// Applies the forces of the `ForceField` volumes to the dynamic bodies inside
+/// them.
+///
+/// A body is inside when its center is.
+/// The buoyancy treats every body as its bounding sphere, so the submerged
+/// fraction of a box or of a capsule lying on its side is only approximated.
+/// The gravity wells and the buoyancy scale with the body `Mass`, so the bodies
+/// without it get only the wind.
+/// It runs before each physics step, since the forces are cleared at each step.
+#[derive(Debug, Default)]
+pub struct ForceFieldSystem;
+
+impl<'s> System<'s> for ForceFieldSystem {
+    type SystemData = (
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        Read<'s, ShapeRegistry>,
+        ReadStorage<'s, ForceField>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
+        ReadStorage<'s, PhysicsHandle<PhysicsShapeTag>>,
+        ReadStorage<'s, Mass>,
+    );
+
+    fn run(
+        &mut self,
+        (physics_world, shape_registry, force_fields, transforms, bodies, shapes, masses): Self::SystemData,
+    ) {
+        let rb_server = physics_world.rigid_body_server();
+        let gravity = physics_world.world_server().gravity();
+
+        for (field, field_transform) in (&force_fields, &transforms).join() {
+            let center = *field_transform.translation();
+
+            for (body, shape, mass) in (&bodies, (&shapes).maybe(), (&masses).maybe()).join() {
+                let body = body.get();
+                if rb_server.mode(body) != BodyMode::Dynamic {
+                    continue;
+                }
+
+                let position = rb_server.transform(body).translation.vector;
+                let offset = position - center;
+                let is_inside = match field.volume {
+                    ForceVolume::Box { half_extents } => {
+                        offset.x.abs() <= half_extents.x
+                            && offset.y.abs() <= half_extents.y
+                            && offset.z.abs() <= half_extents.z
+                    }
+                    ForceVolume::Sphere { radius } => offset.norm() <= radius,
+                };
+                if !is_inside {
+                    continue;
+                }
+
+                let force = match (field.kind, mass) {
+                    (ForceFieldKind::Wind { force }, _) => force,
+                    (_, None) => continue,
+                    (ForceFieldKind::GravityWell { strength }, Some(&Mass(mass))) => {
+                        let distance = offset.norm();
+                        if distance <= std::f32::EPSILON {
+                            continue;
+                        }
+                        let falloff = 1.0 - distance / field.volume.extent(&offset);
+                        -offset / distance * strength * falloff.max(0.0) * mass
+                    }
+                    (ForceFieldKind::Buoyancy { density, drag }, Some(&Mass(mass))) => {
+                        let surface = center.y + field.volume.extent(&Vector3::y());
+                        let radius = shape
+                            .and_then(|s| shape_registry.desc(s.get()))
+                            .map_or(0.5, visual_utils::bounding_radius);
+                        let depth = surface - position.y;
+                        let submerged = ((depth + radius) / (2.0 * radius)).min(1.0).max(0.0);
+
+                        let buoyancy = -gravity * density * submerged * mass;
+                        let drag = -rb_server.linear_velocity(body) * drag * submerged * mass;
+                        buoyancy + drag
+                    }
+                };
+                rb_server.apply_force(body, &force);
+            }
+        }
+    }
+}
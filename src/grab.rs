@@ -0,0 +1,147 @@
+use amethyst::{
+    core::math::Vector3,
+    ecs::prelude::*,
+    input::{InputHandler, StringBindings},
+};
+use amethyst_physics::prelude::*;
+
+use crate::{
+    components::{CharacterBody, Mass},
+    resources::MousePicking,
+};
+
+/// Bodies farther than this from the character can't be grabbed.
+const GRAB_MAX_DISTANCE: f32 = 4.0;
+/// Heavier bodies can't be grabbed.
+const GRAB_MAX_MASS: f32 = 20.0;
+/// Distance in front of the character, along the view ray, where the body is held.
+const HOLD_DISTANCE: f32 = 2.5;
+/// How fast the held body is pulled toward the hold point, in 1/s.
+const HOLD_STIFFNESS: f32 = 12.0;
+/// Max speed of the held body while it follows the hold point.
+const HOLD_MAX_SPEED: f32 = 20.0;
+/// Spin speed of the held body, in rad/s.
+const ROTATE_SPEED: f32 = 2.0;
+const THROW_IMPULSE: f32 = 15.0;
+
+/// Physics gun: grabs the picked dynamic body, carries it in front of the
+/// camera and throws it.
+///
+/// - `Grab` picks the body under the cursor, and releases it when pressed again.
+/// - `RotateHeldLeft` / `RotateHeldRight` spin the held body around the up axis.
+/// - `Throw` releases the held body with an impulse along the view ray.
+///
+/// The body follows the hold point through a velocity spring, so it still
+/// collides with the rest of the world while carried.
+/// Static, kinematic and too heavy bodies, and the ones without a `Mass`,
+/// can't be grabbed.
+///
+/// The distances are measured from the character rather than from the camera,
+/// which sits on the boom behind it.
+/// It runs before each physics step, and uses the ray of the last
+/// `MousePickingSystem` run.
+#[derive(Debug, Default)]
+pub struct GrabSystem {
+    held: Option<Entity>,
+    was_grab_down: bool,
+    was_throw_down: bool,
+}
+
+impl<'s> System<'s> for GrabSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, MousePicking>,
+        ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
+        ReadStorage<'s, Mass>,
+        ReadStorage<'s, CharacterBody>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, physics_world, input, picking, bodies, masses, characters): Self::SystemData,
+    ) {
+        let rb_server = physics_world.rigid_body_server();
+
+        let grab_down = input.action_is_down("Grab").unwrap_or(false);
+        let throw_down = input.action_is_down("Throw").unwrap_or(false);
+        let grab_pressed = grab_down && !self.was_grab_down;
+        let throw_pressed = throw_down && !self.was_throw_down;
+        self.was_grab_down = grab_down;
+        self.was_throw_down = throw_down;
+
+        // Drop the body if it was deleted, or it's not dynamic anymore.
+        self.held = self.held.filter(|e| {
+            entities.is_alive(*e)
+                && bodies
+                    .get(*e)
+                    .map_or(false, |b| rb_server.mode(b.get()) == BodyMode::Dynamic)
+        });
+
+        let ray = match &picking.ray {
+            Some(ray) => *ray,
+            None => return,
+        };
+        let character_position = match (&bodies, &characters).join().next() {
+            Some((body, _)) => rb_server.transform(body.get()).translation.vector,
+            None => return,
+        };
+
+        let held = match self.held {
+            Some(held) => held,
+            None => {
+                if grab_pressed {
+                    self.held = picking
+                        .hit
+                        .filter(|hit| {
+                            (hit.point.coords - character_position).norm() <= GRAB_MAX_DISTANCE
+                        })
+                        .filter(|hit| {
+                            let is_dynamic = bodies
+                                .get(hit.entity)
+                                .map_or(false, |b| rb_server.mode(b.get()) == BodyMode::Dynamic);
+                            let is_light = masses
+                                .get(hit.entity)
+                                .map_or(false, |mass| mass.0 <= GRAB_MAX_MASS);
+                            is_dynamic && is_light
+                        })
+                        .map(|hit| hit.entity);
+                }
+                return;
+            }
+        };
+        let body = bodies.get(held).unwrap().get();
+
+        if grab_pressed {
+            self.held = None;
+            return;
+        }
+
+        if throw_pressed {
+            self.held = None;
+            rb_server.set_angular_velocity(body, &Vector3::zeros());
+            rb_server.apply_impulse(body, &(ray.direction * THROW_IMPULSE));
+            return;
+        }
+
+        // The point of the view ray closest to the character, moved forward.
+        let character_distance = (character_position - ray.origin.coords).dot(&ray.direction);
+        let target = ray.origin + ray.direction * (character_distance + HOLD_DISTANCE);
+        let position = rb_server.transform(body).translation.vector;
+        let mut velocity = (target.coords - position) * HOLD_STIFFNESS;
+        if velocity.norm() > HOLD_MAX_SPEED {
+            velocity = velocity.normalize() * HOLD_MAX_SPEED;
+        }
+        rb_server.set_linear_velocity(body, &velocity);
+
+        let mut spin = 0.0;
+        if input.action_is_down("RotateHeldLeft").unwrap_or(false) {
+            spin += ROTATE_SPEED;
+        }
+        if input.action_is_down("RotateHeldRight").unwrap_or(false) {
+            spin -= ROTATE_SPEED;
+        }
+        rb_server.set_angular_velocity(body, &Vector3::new(0.0, spin, 0.0));
+    }
+}
@@ -0,0 +1,733 @@
+use std::collections::HashMap;
+
+use amethyst::{
+    core::{
+        math::{Isometry3, UnitQuaternion, Vector3},
+        Transform,
+    },
+    ecs::prelude::*,
+    shrev::EventChannel,
+};
+use amethyst_physics::prelude::*;
+
+use crate::{
+    components::{Joint, JointKind, JointMotor, Mass},
+    resources::ShapeRegistry,
+    visual_utils,
+};
+
+/// Velocity iterations of the joint solver, at each physics step.
+const SOLVER_ITERATIONS: usize = 8;
+/// Fraction of the joint error corrected at each physics step.
+const ERROR_REDUCTION: f32 = 0.2;
+/// Radius used for the inertia of the bodies without a known shape.
+const DEFAULT_RADIUS: f32 = 0.5;
+/// Contacts reported by the bodies of the breakable fixed joints.
+const BREAKABLE_JOINT_CONTACTS: usize = 8;
+
+/// Published on the `EventChannel<JointBroken>` when a joint breaks; its `Joint`
+/// component is already removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JointBroken {
+    /// The entity that had the `Joint` component.
+    pub entity: Entity,
+    pub other: Entity,
+}
+
+/// Force, in newtons, applied by each joint solved by the `JointSystem` in the
+/// last physics step.
+#[derive(Debug, Default)]
+pub struct JointLoads {
+    forces: HashMap<Entity, f32>,
+}
+
+impl JointLoads {
+    /// Force of the joint of this entity; `None` when it wasn't solved.
+    pub fn force(&self, entity: Entity) -> Option<f32> {
+        self.forces.get(&entity).copied()
+    }
+}
+
+/// Creates the physics joints of the `Joint` components, and removes them when
+/// the component, or one of the two entities, is removed.
+///
+/// The physics servers expose only the fixed joints, so the hinge, ball and
+/// prismatic joints are solved here: before each physics step the velocities
+/// of the two bodies are corrected so they respect the joint, its limits and
+/// its motor. Their inertia is approximated with a sphere as large as their
+/// shape, and the bodies without a `Mass` are treated as static.
+/// The force applied by each of these joints is stored in the `JointLoads`.
+///
+/// The fixed joint handle is added to both entities, so an entity can be part
+/// of a single fixed joint; the other fixed joints are ignored.
+///
+/// The joint frames are taken from the entity `Transform`s, since the bodies
+/// may be not synchronized yet when the joint is created.
+/// It runs before each physics step, so a broken joint is removed right away.
+#[derive(Default)]
+pub struct JointSystem {
+    /// The other entity of the created fixed joints; the joint is removed with
+    /// the handles of the two entities.
+    joints: HashMap<Entity, Entity>,
+    /// The joints solved by this system.
+    constraints: HashMap<Entity, Constraint>,
+}
+
+/// Joint solved by the `JointSystem`.
+#[derive(Debug, Clone)]
+struct Constraint {
+    other: Entity,
+    kind: JointKind,
+    /// Joint frame, relative to the entity body.
+    frame: Isometry3<f32>,
+    /// Joint frame, relative to the other body.
+    other_frame: Isometry3<f32>,
+}
+
+impl<'s> System<'s> for JointSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'s>,
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        ReadExpect<'s, PhysicsTime>,
+        Read<'s, ShapeRegistry>,
+        Write<'s, JointLoads>,
+        ReadStorage<'s, Joint>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
+        ReadStorage<'s, PhysicsHandle<PhysicsShapeTag>>,
+        WriteStorage<'s, PhysicsHandle<PhysicsJointTag>>,
+        ReadStorage<'s, Mass>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            physics_world,
+            physics_time,
+            shape_registry,
+            mut joint_loads,
+            joints,
+            transforms,
+            rigid_body_tags,
+            shape_tags,
+            mut joint_handles,
+            masses,
+        ): Self::SystemData,
+    ) {
+        // Drop the joints whose component, or bodies, were removed.
+        let is_alive = |entity: Entity, other: Entity| {
+            joints
+                .get(entity)
+                .map_or(false, |joint| joint.other == other)
+                && rigid_body_tags.contains(entity)
+                && rigid_body_tags.contains(other)
+        };
+        let removed: Vec<Entity> = self
+            .joints
+            .iter()
+            .filter(|(entity, other)| !is_alive(**entity, **other))
+            .map(|(entity, _)| *entity)
+            .collect();
+        for entity in removed {
+            if let Some(other) = self.joints.remove(&entity) {
+                joint_handles.remove(entity);
+                joint_handles.remove(other);
+            }
+        }
+        self.constraints
+            .retain(|entity, constraint| is_alive(*entity, constraint.other));
+
+        let joint_server = physics_world.joint_server();
+        for (entity, joint, body, transform) in
+            (&entities, &joints, &rigid_body_tags, &transforms).join()
+        {
+            if joint.other == entity
+                || self.joints.contains_key(&entity)
+                || self.constraints.contains_key(&entity)
+            {
+                continue;
+            }
+            // The other body may be still waiting to be created.
+            let (other_body, other_transform) = match (
+                rigid_body_tags.get(joint.other),
+                transforms.get(joint.other),
+            ) {
+                (Some(other_body), Some(other_transform)) => (other_body, other_transform),
+                _ => continue,
+            };
+
+            let frame = transform.isometry() * joint.anchor;
+            if let JointKind::Fixed = joint.kind {
+                if joint_handles.contains(entity) || joint_handles.contains(joint.other) {
+                    continue;
+                }
+                // The bodies are added to the joint when the handles are inserted.
+                let physics_joint =
+                    joint_server.create(&JointDesc::Fixed, JointPosition::Exact(frame));
+                joint_handles
+                    .insert(joint.other, physics_joint.clone())
+                    .expect("The entity is alive");
+                joint_handles
+                    .insert(entity, physics_joint)
+                    .expect("The entity is alive");
+
+                // The `JointBreakSystem` estimates the force from the contacts.
+                if joint.break_force.is_some() {
+                    let rb_server = physics_world.rigid_body_server();
+                    for tag in &[body.get(), other_body.get()] {
+                        let count = rb_server
+                            .contacts_to_report(*tag)
+                            .max(BREAKABLE_JOINT_CONTACTS);
+                        rb_server.set_contacts_to_report(*tag, count);
+                    }
+                }
+
+                self.joints.insert(entity, joint.other);
+            } else {
+                self.constraints.insert(
+                    entity,
+                    Constraint {
+                        other: joint.other,
+                        kind: joint.kind,
+                        frame: joint.anchor,
+                        other_frame: other_transform.isometry().inverse() * frame,
+                    },
+                );
+            }
+        }
+
+        joint_loads.forces.clear();
+        let delta = physics_time.delta_seconds();
+        if self.constraints.is_empty() || delta <= 0.0 {
+            return;
+        }
+
+        let rb_server = physics_world.rigid_body_server();
+        let gravity = physics_world.world_server().gravity();
+
+        // The bodies of the joints, with the velocity they will have after the
+        // gravity of the next step.
+        let mut bodies: Vec<SolverBody> = Vec::new();
+        let mut indices = HashMap::new();
+        let mut body_index = |entity: Entity, bodies: &mut Vec<SolverBody>| {
+            if let Some(index) = indices.get(&entity) {
+                return Some(*index);
+            }
+            let tag = rigid_body_tags.get(entity)?.get();
+            let mass = masses.get(entity).map_or(0.0, |mass| mass.0);
+            let dynamic = rb_server.mode(tag) == BodyMode::Dynamic && mass > 0.0;
+            let mut body = SolverBody {
+                tag,
+                pose: rb_server.transform(tag),
+                linear: rb_server.linear_velocity(tag),
+                angular: rb_server.angular_velocity(tag),
+                inv_mass: 0.0,
+                inv_inertia: 0.0,
+            };
+            if dynamic {
+                let radius = shape_tags
+                    .get(entity)
+                    .and_then(|shape| shape_registry.desc(shape.get()))
+                    .map_or(DEFAULT_RADIUS, visual_utils::bounding_radius)
+                    .max(0.05);
+                body.linear += gravity * delta;
+                body.inv_mass = 1.0 / mass;
+                body.inv_inertia = 1.0 / (0.4 * mass * radius * radius);
+            }
+            indices.insert(entity, bodies.len());
+            bodies.push(body);
+            Some(bodies.len() - 1)
+        };
+
+        let mut rows = Vec::new();
+        // The rows of each joint.
+        let mut joint_rows = Vec::with_capacity(self.constraints.len());
+        for (entity, constraint) in &self.constraints {
+            let (a, b) = match (
+                body_index(*entity, &mut bodies),
+                body_index(constraint.other, &mut bodies),
+            ) {
+                (Some(a), Some(b)) => (a, b),
+                _ => continue,
+            };
+            if bodies[a].is_static() && bodies[b].is_static() {
+                continue;
+            }
+            let first_row = rows.len();
+            constraint.rows(a, b, &bodies, delta, &mut rows);
+            joint_rows.push((*entity, first_row..rows.len()));
+        }
+
+        for row in &mut rows {
+            let (a, b) = (&bodies[row.a], &bodies[row.b]);
+            let k = a.inv_mass * row.linear_a.norm_squared()
+                + a.inv_inertia * row.angular_a.norm_squared()
+                + b.inv_mass * row.linear_b.norm_squared()
+                + b.inv_inertia * row.angular_b.norm_squared();
+            row.effective_mass = if k > std::f32::EPSILON { 1.0 / k } else { 0.0 };
+        }
+
+        for _ in 0..SOLVER_ITERATIONS {
+            for row in &mut rows {
+                row.solve(&mut bodies);
+            }
+        }
+
+        // Only the linear rows apply a force; the torques aren't counted.
+        for (entity, range) in joint_rows {
+            let impulse = rows[range].iter().fold(Vector3::zeros(), |sum, row| {
+                sum + row.linear_b * row.impulse
+            });
+            joint_loads.forces.insert(entity, impulse.norm() / delta);
+        }
+
+        for body in bodies.iter().filter(|b| !b.is_static()) {
+            rb_server.set_linear_velocity(body.tag, &(body.linear - gravity * delta));
+            rb_server.set_angular_velocity(body.tag, &body.angular);
+        }
+    }
+}
+
+/// Body state used by the joint solver; the static and kinematic bodies have
+/// no inverse mass, so the joints don't move them.
+struct SolverBody {
+    tag: PhysicsRigidBodyTag,
+    pose: Isometry3<f32>,
+    linear: Vector3<f32>,
+    angular: Vector3<f32>,
+    inv_mass: f32,
+    inv_inertia: f32,
+}
+
+impl SolverBody {
+    fn is_static(&self) -> bool {
+        self.inv_mass <= 0.0
+    }
+}
+
+/// Constraint on the velocity of two bodies along a single direction:
+/// `linear_a . va + angular_a . wa + linear_b . vb + angular_b . wb + bias = 0`,
+/// with the impulse kept inside `min_impulse..max_impulse`.
+struct Row {
+    a: usize,
+    b: usize,
+    linear_a: Vector3<f32>,
+    angular_a: Vector3<f32>,
+    linear_b: Vector3<f32>,
+    angular_b: Vector3<f32>,
+    bias: f32,
+    min_impulse: f32,
+    max_impulse: f32,
+    impulse: f32,
+    effective_mass: f32,
+}
+
+impl Row {
+    /// Keeps the point at `offset_b` of `b` on the point at `offset_a` of `a`,
+    /// along `direction`; `error` is their current distance along it.
+    fn linear(
+        a: usize,
+        b: usize,
+        offset_a: &Vector3<f32>,
+        offset_b: &Vector3<f32>,
+        direction: &Vector3<f32>,
+        bias: f32,
+    ) -> Self {
+        Row {
+            a,
+            b,
+            linear_a: -direction,
+            angular_a: -offset_a.cross(direction),
+            linear_b: *direction,
+            angular_b: offset_b.cross(direction),
+            bias,
+            min_impulse: std::f32::MIN,
+            max_impulse: std::f32::MAX,
+            impulse: 0.0,
+            effective_mass: 0.0,
+        }
+    }
+
+    /// Keeps the angular velocity of `b`, relative to `a`, around `axis`.
+    fn angular(a: usize, b: usize, axis: &Vector3<f32>, bias: f32) -> Self {
+        Row {
+            a,
+            b,
+            linear_a: Vector3::zeros(),
+            angular_a: -axis,
+            linear_b: Vector3::zeros(),
+            angular_b: *axis,
+            bias,
+            min_impulse: std::f32::MIN,
+            max_impulse: std::f32::MAX,
+            impulse: 0.0,
+            effective_mass: 0.0,
+        }
+    }
+
+    fn with_impulse_range(mut self, min: f32, max: f32) -> Self {
+        self.min_impulse = min;
+        self.max_impulse = max;
+        self
+    }
+
+    fn solve(&mut self, bodies: &mut [SolverBody]) {
+        let velocity = {
+            let (a, b) = (&bodies[self.a], &bodies[self.b]);
+            self.linear_a.dot(&a.linear)
+                + self.angular_a.dot(&a.angular)
+                + self.linear_b.dot(&b.linear)
+                + self.angular_b.dot(&b.angular)
+        };
+
+        let previous = self.impulse;
+        self.impulse = (previous - (velocity + self.bias) * self.effective_mass)
+            .max(self.min_impulse)
+            .min(self.max_impulse);
+        let impulse = self.impulse - previous;
+
+        let a = &mut bodies[self.a];
+        a.linear += self.linear_a * (a.inv_mass * impulse);
+        a.angular += self.angular_a * (a.inv_inertia * impulse);
+        let b = &mut bodies[self.b];
+        b.linear += self.linear_b * (b.inv_mass * impulse);
+        b.angular += self.angular_b * (b.inv_inertia * impulse);
+    }
+}
+
+impl Constraint {
+    /// Adds the rows of the joint between the bodies `a` and `b`.
+    fn rows(&self, a: usize, b: usize, bodies: &[SolverBody], delta: f32, rows: &mut Vec<Row>) {
+        let frame_a = bodies[a].pose * self.frame;
+        let frame_b = bodies[b].pose * self.other_frame;
+        let offset_a = frame_a.translation.vector - bodies[a].pose.translation.vector;
+        let offset_b = frame_b.translation.vector - bodies[b].pose.translation.vector;
+        let separation = frame_b.translation.vector - frame_a.translation.vector;
+        // Rotation of the frame of `b` relative to the frame of `a`.
+        let rotation = frame_b.rotation * frame_a.rotation.inverse();
+        let correction = ERROR_REDUCTION / delta;
+
+        match self.kind {
+            JointKind::Fixed => {}
+            JointKind::Hinge {
+                axis,
+                limits,
+                motor,
+            } => {
+                push_point_rows(a, b, &offset_a, &offset_b, &separation, correction, rows);
+
+                let axis_a = frame_a.rotation * axis.normalize();
+                let axis_b = frame_b.rotation * axis.normalize();
+                let error = axis_a.cross(&axis_b);
+                let (t1, t2) = perpendiculars(&axis_a);
+                rows.push(Row::angular(a, b, &t1, error.dot(&t1) * correction));
+                rows.push(Row::angular(a, b, &t2, error.dot(&t2) * correction));
+
+                let angle = twist_angle(&rotation, &axis_a);
+                if let Some(row) = limit_row(limits, angle, correction, |bias| {
+                    Row::angular(a, b, &axis_a, bias)
+                }) {
+                    rows.push(row);
+                }
+                if let Some(JointMotor {
+                    target_velocity,
+                    max_force,
+                }) = motor
+                {
+                    rows.push(
+                        Row::angular(a, b, &axis_a, -target_velocity)
+                            .with_impulse_range(-max_force * delta, max_force * delta),
+                    );
+                }
+            }
+            JointKind::Ball { swing_limit } => {
+                push_point_rows(a, b, &offset_a, &offset_b, &separation, correction, rows);
+
+                if let Some(swing_limit) = swing_limit {
+                    let y_a = frame_a.rotation * Vector3::y();
+                    let y_b = frame_b.rotation * Vector3::y();
+                    let angle = y_a.dot(&y_b).max(-1.0).min(1.0).acos();
+                    let axis = y_a.cross(&y_b);
+                    if angle > swing_limit && axis.norm() > std::f32::EPSILON {
+                        rows.push(
+                            Row::angular(
+                                a,
+                                b,
+                                &axis.normalize(),
+                                (angle - swing_limit) * correction,
+                            )
+                            .with_impulse_range(std::f32::MIN, 0.0),
+                        );
+                    }
+                }
+            }
+            JointKind::Prismatic {
+                axis,
+                limits,
+                motor,
+            } => {
+                let error = rotation.scaled_axis();
+                for direction in &[Vector3::x(), Vector3::y(), Vector3::z()] {
+                    rows.push(Row::angular(
+                        a,
+                        b,
+                        direction,
+                        error.dot(direction) * correction,
+                    ));
+                }
+
+                // The anchor of `a` slides along the axis, up to the anchor of `b`.
+                let offset_a = offset_a + separation;
+                let axis_a = frame_a.rotation * axis.normalize();
+                let (t1, t2) = perpendiculars(&axis_a);
+                for direction in &[t1, t2] {
+                    rows.push(Row::linear(
+                        a,
+                        b,
+                        &offset_a,
+                        &offset_b,
+                        direction,
+                        separation.dot(direction) * correction,
+                    ));
+                }
+
+                let position = separation.dot(&axis_a);
+                if let Some(row) = limit_row(limits, position, correction, |bias| {
+                    Row::linear(a, b, &offset_a, &offset_b, &axis_a, bias)
+                }) {
+                    rows.push(row);
+                }
+                if let Some(JointMotor {
+                    target_velocity,
+                    max_force,
+                }) = motor
+                {
+                    rows.push(
+                        Row::linear(a, b, &offset_a, &offset_b, &axis_a, -target_velocity)
+                            .with_impulse_range(-max_force * delta, max_force * delta),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Adds the rows that keep the two anchors on the same point.
+fn push_point_rows(
+    a: usize,
+    b: usize,
+    offset_a: &Vector3<f32>,
+    offset_b: &Vector3<f32>,
+    separation: &Vector3<f32>,
+    correction: f32,
+    rows: &mut Vec<Row>,
+) {
+    for direction in &[Vector3::x(), Vector3::y(), Vector3::z()] {
+        rows.push(Row::linear(
+            a,
+            b,
+            offset_a,
+            offset_b,
+            direction,
+            separation.dot(direction) * correction,
+        ));
+    }
+}
+
+/// Row that pushes `value` back inside `limits`, when it's out of them.
+fn limit_row(
+    limits: Option<(f32, f32)>,
+    value: f32,
+    correction: f32,
+    row: impl FnOnce(f32) -> Row,
+) -> Option<Row> {
+    let (min, max) = limits?;
+    if value < min {
+        Some(row((value - min) * correction).with_impulse_range(0.0, std::f32::MAX))
+    } else if value > max {
+        Some(row((value - max) * correction).with_impulse_range(std::f32::MIN, 0.0))
+    } else {
+        None
+    }
+}
+
+/// Two unit vectors orthogonal to `axis` and to each other.
+fn perpendiculars(axis: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let reference = if axis.x.abs() < 0.57 {
+        Vector3::x()
+    } else {
+        Vector3::y()
+    };
+    let t1 = axis.cross(&reference).normalize();
+    (t1, axis.cross(&t1))
+}
+
+/// Angle of `rotation` around `axis`, in `-PI..PI`.
+fn twist_angle(rotation: &UnitQuaternion<f32>, axis: &Vector3<f32>) -> f32 {
+    let q = rotation.quaternion();
+    let (w, imag) = if q.w < 0.0 {
+        (-q.w, -q.imag())
+    } else {
+        (q.w, q.imag())
+    };
+    2.0 * imag.dot(axis).atan2(w)
+}
+
+/// Breaks the joints whose force goes over their `break_force`.
+///
+/// The force of the joints solved by the `JointSystem` is the impulse applied
+/// by its solver over the step duration.
+/// The servers don't report the force of the fixed joints, so it's estimated
+/// from the contact impulses of the two bodies: since they move together, the
+/// joint moves to each body its share of the impulses the other one received.
+/// The bodies without a `Mass` never break their fixed joints.
+///
+/// It runs after each physics step.
+#[derive(Debug, Default)]
+pub struct JointBreakSystem {
+    contact_events: Vec<ContactEvent<f32>>,
+}
+
+impl JointBreakSystem {
+    /// Sum of the contact impulses of the entity body, except the ones with
+    /// `other`, and its mass.
+    fn contact_impulse(
+        &mut self,
+        rb_server: &dyn RBodyPhysicsServerTrait<f32>,
+        entity: Entity,
+        other: Entity,
+        rigid_body_tags: &ReadStorage<'_, PhysicsHandle<PhysicsRigidBodyTag>>,
+        masses: &ReadStorage<'_, Mass>,
+    ) -> Option<(Vector3<f32>, f32)> {
+        let body = rigid_body_tags.get(entity)?.get();
+        let mass = masses.get(entity)?.0;
+        rb_server.contact_events(body, &mut self.contact_events);
+        let impulse = self
+            .contact_events
+            .iter()
+            .filter(|contact| contact.other_entity != Some(other))
+            .fold(Vector3::zeros(), |sum, contact| sum + contact.impulse);
+        Some((impulse, mass))
+    }
+}
+
+impl<'s> System<'s> for JointBreakSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'s>,
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        ReadExpect<'s, PhysicsTime>,
+        Read<'s, JointLoads>,
+        Write<'s, EventChannel<JointBroken>>,
+        WriteStorage<'s, Joint>,
+        ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
+        ReadStorage<'s, Mass>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            physics_world,
+            physics_time,
+            joint_loads,
+            mut joint_broken_events,
+            mut joints,
+            rigid_body_tags,
+            masses,
+        ): Self::SystemData,
+    ) {
+        let rb_server = physics_world.rigid_body_server();
+        let delta = physics_time.delta_seconds();
+        if delta <= 0.0 {
+            return;
+        }
+
+        let mut broken = Vec::new();
+        for (entity, joint) in (&entities, &joints).join() {
+            let break_force = match joint.break_force {
+                Some(break_force) => break_force,
+                None => continue,
+            };
+
+            let force = if let JointKind::Fixed = joint.kind {
+                let a =
+                    self.contact_impulse(rb_server, entity, joint.other, &rigid_body_tags, &masses);
+                let b =
+                    self.contact_impulse(rb_server, joint.other, entity, &rigid_body_tags, &masses);
+                match (a, b) {
+                    (Some(a), Some(b)) => transferred_impulse(a, b) / delta,
+                    _ => 0.0,
+                }
+            } else {
+                joint_loads.force(entity).unwrap_or(0.0)
+            };
+
+            if force > break_force {
+                broken.push(entity);
+            }
+        }
+
+        for entity in broken {
+            if let Some(joint) = joints.remove(entity) {
+                joint_broken_events.single_write(JointBroken {
+                    entity,
+                    other: joint.other,
+                });
+            }
+        }
+    }
+}
+
+/// Impulse that a fixed joint applies, so two bodies receiving the impulses
+/// `a` and `b` move together; each one is given with the body mass.
+fn transferred_impulse(
+    (impulse_a, mass_a): (Vector3<f32>, f32),
+    (impulse_b, mass_b): (Vector3<f32>, f32),
+) -> f32 {
+    let total_mass = mass_a + mass_b;
+    if total_mass <= 0.0 {
+        return 0.0;
+    }
+    ((impulse_b * mass_a - impulse_a * mass_b) / total_mass).norm()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn twist_angle_ignores_the_swing() {
+        let twist = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.5);
+        let swing = UnitQuaternion::from_axis_angle(&Vector3::x_axis(), 0.3);
+        assert!((twist_angle(&twist, &Vector3::y()) - 0.5).abs() < 1e-5);
+        assert!((twist_angle(&twist.inverse(), &Vector3::y()) + 0.5).abs() < 1e-5);
+        assert!(twist_angle(&swing, &Vector3::y()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn limit_row_pushes_back_inside_the_limits() {
+        let row = |bias| Row::angular(0, 1, &Vector3::y(), bias);
+        assert!(limit_row(Some((-1.0, 1.0)), 0.5, 10.0, row).is_none());
+        assert!(limit_row(None, 5.0, 10.0, row).is_none());
+
+        let below = limit_row(Some((-1.0, 1.0)), -1.5, 10.0, row).unwrap();
+        assert!(below.min_impulse >= 0.0);
+        assert!(below.bias < 0.0);
+        let above = limit_row(Some((-1.0, 1.0)), 1.5, 10.0, row).unwrap();
+        assert!(above.max_impulse <= 0.0);
+        assert!(above.bias > 0.0);
+    }
+
+    #[test]
+    fn transferred_impulse_is_zero_when_both_bodies_are_hit_alike() {
+        let hit = (Vector3::new(0.0, 5.0, 0.0), 1.0);
+        assert!(transferred_impulse(hit, hit) < 1e-5);
+
+        let rest = (Vector3::zeros(), 1.0);
+        assert!((transferred_impulse(hit, rest) - 2.5).abs() < 1e-5);
+        assert!((transferred_impulse(rest, hit) - 2.5).abs() < 1e-5);
+    }
+}
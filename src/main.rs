@@ -1,8 +1,9 @@
 use amethyst::{
+    assets::{PrefabLoader, PrefabLoaderSystemDesc, RonFormat},
+    config::Config,
     controls::{CursorHideSystem, MouseFocusUpdateSystemDesc},
     core::{
-        math::Vector3,
-        timing::Time,
+        math::{Isometry3, Vector3},
         transform::{Transform, TransformBundle},
         Parent,
     },
@@ -10,85 +11,97 @@ use amethyst::{
     prelude::*,
     renderer::{
         camera::Camera,
-        light,
-        palette::{LinSrgba, Srgb},
-        plugins::{RenderShaded3D, RenderToWindow},
-        rendy::mesh::{Normal, Position, Tangent, TexCoord},
-        shape::Shape,
-        types,
-        visibility::BoundingSphere,
-        RenderingBundle,
+        palette::LinSrgba,
+        plugins::{RenderDebugLines, RenderShaded3D, RenderToWindow},
+        types, RenderingBundle,
     },
+    ui::{RenderUi, UiBundle, UiCreator},
     utils::application_root_dir,
     window::ScreenDimensions,
     Error,
 };
-use rand::prelude::*;
 
 use amethyst_nphysics::NPhysicsBackend;
 use amethyst_physics::{prelude::*, PhysicsBundle};
 
 mod components;
+mod convex_decomposition;
+mod debug_draw;
+mod entity_builder;
+mod explosion;
+mod force_field;
+mod grab;
+mod joint;
+mod mesh_collider;
+mod picking;
+mod prefab;
+mod ragdoll;
+mod resources;
+mod scene;
 mod systems;
+mod terrain;
+mod trigger;
 mod visual_utils;
 
+use entity_builder::PhysicsEntityBuilder;
+use mesh_collider::MeshColliderKind;
+use resources::ShapeRegistry;
+use scene::{BodyDesc, CompoundPart, SceneBodyMode, SceneShape, VisualDesc};
+
 #[derive(Default)]
-struct Example {
-    time_bank: f32,
-}
+struct Example;
 
 impl SimpleState for Example {
     fn on_start(&mut self, data: StateData<'_, GameData<'_, '_>>) {
-        // Add light
-        add_light_entity(
-            data.world,
-            Srgb::new(1.0, 1.0, 1.0),
-            Vector3::new(-0.2, -1.0, -0.2),
-            1.0,
-        );
-        add_light_entity(
-            data.world,
-            Srgb::new(1.0, 0.8, 0.8),
-            Vector3::new(0.2, -1.0, 0.2),
-            1.0,
-        );
-
-        // Create floor
-        create_floor(data.world);
+        data.world.insert(ShapeRegistry::default());
+
+        // Create lights, floor and props.
+        let scene_path = application_root_dir()
+            .expect("The application root is known")
+            .join("assets")
+            .join("scenes")
+            .join("test_level.ron");
+        let scene = scene::load_scene(data.world, scene_path).expect("Failed to load the scene");
 
         // Create the character + camera.
-        create_character_entity(data.world);
+        create_character_entity(data.world, Vector3::from(scene.player_spawn));
 
-        // Create Box
-        add_cube_entity(data.world, Vector3::new(0.0, 6.0, 0.0));
-    }
+        // Create the physics props declared as prefab.
+        let prefab = data
+            .world
+            .exec(|loader: PrefabLoader<'_, prefab::PhysicsPrefabData>| {
+                loader.load("prefab/physics_spheres.ron", RonFormat, ())
+            });
+        data.world.create_entity().with(prefab).build();
 
-    fn update(&mut self, data: &mut StateData<'_, GameData<'_, '_>>) -> SimpleTrans {
-        // TODO this code must go inside a System.
-        // Spawn a new cube each X sec.
-        {
-            let time = data.world.fetch::<Time>();
-            self.time_bank += time.delta_seconds();
-        }
+        // Create a table, made of a compound collider.
+        create_table(data.world, Vector3::new(-8.0, 1.0, -8.0));
 
-        let time_threshold = 10.0; // Each 10 sec
-        let spawn_scale = 10.0f32; // Scale
+        // Create the props with a collider generated from their mesh.
+        create_mesh_props(data.world);
 
-        let mut rng = rand::thread_rng();
+        // Create the cube spawner
+        create_cube_spawner(data.world);
 
-        while self.time_bank > time_threshold {
-            add_cube_entity(
-                data.world,
-                Vector3::new(
-                    rng.gen::<f32>() * spawn_scale - spawn_scale * 0.5,
-                    6.0,
-                    rng.gen::<f32>() * spawn_scale - spawn_scale * 0.5,
-                ),
-            );
-            self.time_bank -= time_threshold;
-        }
+        // Create the sphere fountain
+        create_sphere_fountain(data.world);
 
-        Trans::None
+        // Create two cubes welded by a joint.
+        create_welded_cubes(data.world, Vector3::new(4.0, 3.0, -6.0));
+
+        // Create a crate sliding on a rail.
+        create_slider(data.world, Vector3::new(4.0, 1.0, -10.0));
+
+        // Create the wind, gravity well and water volumes.
+        create_force_fields(data.world);
+
+        // Create the ragdolls, knocked out with `K`.
+        create_ragdolls(data.world);
+
+        // Create the UI that shows the picked body.
+        data.world.exec(|mut creator: UiCreator<'_>| {
+            creator.create("ui/mouse_raycast.ron", ());
+        });
     }
 }
 
@@ -101,6 +114,11 @@ fn main() -> Result<(), Error> {
     let display_config_path = app_root.join("config").join("display.ron");
 
     let game_data = GameDataBuilder::default()
+        .with_system_desc(
+            PrefabLoaderSystemDesc::<prefab::PhysicsPrefabData>::default(),
+            "prefab",
+            &[],
+        )
         .with_system_desc(
             MouseFocusUpdateSystemDesc::default(),
             "mouse_focus_update",
@@ -111,17 +129,47 @@ fn main() -> Result<(), Error> {
             "cursor_hide",
             &["mouse_focus_update"],
         )
+        .with(systems::CubeSpawnerSystem, "cube_spawner", &[])
+        .with(systems::DespawnSystem, "despawn", &[])
+        .with(
+            systems::ShapeRegistryCleanupSystem::default(),
+            "shape_registry_cleanup",
+            &["despawn"],
+        )
+        .with(
+            systems::CharacterRespawnSystem::default(),
+            "character_respawn",
+            &[],
+        )
         .with_bundle(
             InputBundle::<StringBindings>::new()
                 .with_bindings_from_file(assets_dir.join("input_bindings.ron"))
                 .unwrap(),
         )?
+        .with_bundle(UiBundle::<StringBindings>::new())?
         .with(
             systems::CameraMotionSystem::new(),
             "camera_motion_system",
             &["input_system"],
         )
-        .with_bundle(TransformBundle::new())?
+        .with(
+            ragdoll::RagdollKnockoutSystem::default(),
+            "ragdoll_knockout",
+            &["input_system"],
+        )
+        .with(
+            systems::TransformInterpolationSystem::default(),
+            "transform_interpolation",
+            &[],
+        )
+        .with_bundle(TransformBundle::new().with_dep(&["transform_interpolation"]))?
+        .with(
+            systems::TransformInterpolationRestoreSystem,
+            "transform_interpolation_restore",
+            &["transform_system"],
+        )
+        // The physics sync must never read an interpolated `Transform`.
+        .with_barrier()
         .with_bundle(
             PhysicsBundle::<f32, NPhysicsBackend>::new()
                 .with_frames_per_seconds(60)
@@ -130,8 +178,69 @@ fn main() -> Result<(), Error> {
                     systems::CharacterMotionControllerSystem::new(),
                     String::from("character_motion_controller"),
                     vec![],
+                )
+                .with_pre_physics(
+                    force_field::ForceFieldSystem,
+                    String::from("force_field"),
+                    vec![],
+                )
+                .with_pre_physics(grab::GrabSystem::default(), String::from("grab"), vec![])
+                .with_pre_physics(
+                    ragdoll::RagdollSystem::default(),
+                    String::from("ragdoll"),
+                    vec![],
+                )
+                .with_pre_physics(
+                    joint::JointSystem::default(),
+                    String::from("joint"),
+                    vec![String::from("ragdoll"), String::from("grab")],
+                )
+                .with_post_physics(
+                    systems::TransformInterpolationCaptureSystem,
+                    String::from("transform_interpolation_capture"),
+                    vec![],
+                )
+                .with_post_physics(
+                    joint::JointBreakSystem::default(),
+                    String::from("joint_break"),
+                    vec![],
+                )
+                .with_post_physics(
+                    trigger::TriggerEventsSystem,
+                    String::from("trigger_events"),
+                    vec![],
                 ),
         )?
+        .with(
+            debug_draw::PhysicsDebugDrawSystem::default(),
+            "physics_debug_draw",
+            &["input_system"],
+        )
+        .with(
+            trigger::CharacterZoneLogSystem::default(),
+            "character_zone_log",
+            &[],
+        )
+        .with(
+            picking::MousePickingSystem::default(),
+            "mouse_picking",
+            &["transform_system", "input_system"],
+        )
+        .with(
+            picking::MousePickingUiSystem::default(),
+            "mouse_picking_ui",
+            &["mouse_picking"],
+        )
+        .with(
+            explosion::ExplosionDebugSystem::default(),
+            "explosion_debug",
+            &["input_system", "mouse_picking"],
+        )
+        .with(
+            explosion::ExplosionSystem::default(),
+            "explosion",
+            &["explosion_debug"],
+        )
         .with_bundle(
             RenderingBundle::<types::DefaultBackend>::new()
                 .with_plugin(
@@ -139,114 +248,277 @@ fn main() -> Result<(), Error> {
                         .unwrap()
                         .with_clear([0.34, 0.36, 0.52, 1.0]),
                 )
-                .with_plugin(RenderShaded3D::default()),
+                .with_plugin(RenderShaded3D::default())
+                .with_plugin(RenderDebugLines::default())
+                .with_plugin(RenderUi::default()),
         )?;
     let mut game = Application::build(assets_dir, Example::default())?.build(game_data)?;
     game.run();
     Ok(())
 }
 
-fn add_light_entity(world: &mut World, color: Srgb, direction: Vector3<f32>, intensity: f32) {
-    let light: light::Light = light::DirectionalLight {
-        color,
-        direction: direction.normalize(),
-        intensity,
-    }
-    .into();
+/// Creates a dynamic table: the top and the four legs are the parts of a
+/// single compound collider.
+fn create_table(world: &mut World, position: Vector3<f32>) {
+    let leg = |x: f32, z: f32| {
+        CompoundPart::new(
+            SceneShape::Cube {
+                half_extents: [0.1, 0.5, 0.1],
+            },
+            [x, -0.6, z],
+        )
+    };
 
-    world.create_entity().with(light).build();
+    scene::create_body_entity(
+        world,
+        &BodyDesc {
+            shape: SceneShape::Compound {
+                parts: vec![
+                    CompoundPart::new(
+                        SceneShape::Cube {
+                            half_extents: [1.0, 0.1, 0.6],
+                        },
+                        [0.0, 0.0, 0.0],
+                    ),
+                    leg(0.9, 0.5),
+                    leg(-0.9, 0.5),
+                    leg(0.9, -0.5),
+                    leg(-0.9, -0.5),
+                ],
+            },
+            mode: SceneBodyMode::Dynamic,
+            position: [position.x, position.y, position.z],
+            mass: Some(4.0),
+            friction: None,
+            bounciness: None,
+            pushable: true,
+            visual: Some(VisualDesc {
+                color: [0.55, 0.35, 0.2, 1.0],
+                metallic: 0.0,
+                roughness: 1.0,
+            }),
+        },
+    );
 }
 
-fn create_floor(world: &mut World) {
-    let shape = {
-        let desc = ShapeDesc::Cube {
-            half_extents: Vector3::new(20.0, 0.2, 20.0),
-        };
-        let physics_world = world.fetch::<PhysicsWorld<f32>>();
-        physics_world.shape_server().create(&desc)
-    };
+fn create_mesh_props(world: &mut World) {
+    let mesh_dir = application_root_dir()
+        .expect("The application root is known")
+        .join("assets")
+        .join("mesh");
 
-    let rb = {
-        let mut rb_desc = RigidBodyDesc::default();
-        rb_desc.mode = BodyMode::Static;
+    mesh_collider::create_mesh_entity(
+        world,
+        mesh_dir.join("cone.obj"),
+        MeshColliderKind::ConvexHull,
+        BodyMode::Dynamic,
+        Vector3::new(-6.0, 4.0, 6.0),
+        1.0,
+        LinSrgba::new(0.9, 0.9, 0.2, 1.0),
+    )
+    .expect("Failed to load the cone mesh");
 
-        let physics_world = world.fetch::<PhysicsWorld<f32>>();
-        physics_world.rigid_body_server().create(&rb_desc)
-    };
+    mesh_collider::create_mesh_entity(
+        world,
+        mesh_dir.join("Box.gltf"),
+        MeshColliderKind::ConvexHull,
+        BodyMode::Dynamic,
+        Vector3::new(6.0, 4.0, -6.0),
+        1.0,
+        LinSrgba::new(0.2, 0.4, 0.9, 1.0),
+    )
+    .expect("Failed to load the box mesh");
 
-    let mesh = {
-        let mesh_data: types::MeshData = Shape::Cube
-            .generate::<(Vec<Position>, Vec<Normal>, Vec<Tangent>, Vec<TexCoord>)>(Some((
-                20.0, 0.2, 20.0,
-            )))
-            .into();
+    mesh_collider::create_mesh_entity(
+        world,
+        mesh_dir.join("Monster.gltf"),
+        MeshColliderKind::ConvexDecomposition,
+        BodyMode::Dynamic,
+        Vector3::new(8.0, 6.0, 8.0),
+        0.05,
+        LinSrgba::new(0.8, 0.3, 0.3, 1.0),
+    )
+    .expect("Failed to load the monster mesh");
+}
 
-        visual_utils::create_mesh(world, mesh_data)
+/// Creates two cubes welded, side by side, by a fixed joint.
+///
+/// The joint breaks when the cubes are thrown hard.
+fn create_welded_cubes(world: &mut World, position: Vector3<f32>) {
+    let cube = |world: &mut World, position: Vector3<f32>| {
+        PhysicsEntityBuilder::new()
+            .cube(Vector3::new(0.4, 0.4, 0.4))
+            .dynamic()
+            .color(LinSrgba::new(0.3, 0.8, 0.8, 1.0))
+            .at(position)
+            .build(world)
     };
 
-    let mat = visual_utils::create_material(
-        world,
-        LinSrgba::new(0.0, 1.0, 0.0, 1.0),
-        0.0, // Metallic
-        1.0, // Roughness
-    );
+    let first = cube(world, position);
+    let second = cube(world, position + Vector3::new(0.8, 0.0, 0.0));
 
     world
-        .create_entity()
-        .with(mesh)
-        .with(mat)
-        .with(BoundingSphere::origin(20.0))
-        .with(Transform::default())
-        .with(shape)
-        .with(rb)
-        .build();
+        .write_storage()
+        .insert(
+            first,
+            components::Joint::new(second, components::JointKind::Fixed)
+                .with_anchor(Isometry3::translation(0.4, 0.0, 0.0))
+                .with_break_force(400.0),
+        )
+        .expect("The entity is alive");
 }
 
-fn add_cube_entity(world: &mut World, pos: Vector3<f32>) {
-    let shape = {
-        let desc = ShapeDesc::Cube {
-            half_extents: Vector3::new(1.0, 1.0, 1.0),
-        };
-        let physics_world = world.fetch::<PhysicsWorld<f32>>();
-        physics_world.shape_server().create(&desc)
-    };
-
-    let rb = {
-        let rb_desc = RigidBodyDesc::default();
+/// Creates a crate joined to a static post by a prismatic joint: a motor
+/// drags it along the X axis, away from the post, up to the end of its 3 meters
+/// rail.
+fn create_slider(world: &mut World, position: Vector3<f32>) {
+    let post = PhysicsEntityBuilder::new()
+        .cube(Vector3::new(0.2, 1.0, 0.2))
+        .static_body()
+        .color(LinSrgba::new(0.5, 0.5, 0.5, 1.0))
+        .at(position)
+        .build(world);
+    let slider = PhysicsEntityBuilder::new()
+        .cube(Vector3::new(0.4, 0.4, 0.4))
+        .dynamic()
+        .color(LinSrgba::new(0.9, 0.6, 0.2, 1.0))
+        .at(position + Vector3::new(1.0, 0.0, 0.0))
+        .build(world);
 
-        let physics_world = world.fetch::<PhysicsWorld<f32>>();
-        physics_world.rigid_body_server().create(&rb_desc)
-    };
+    world
+        .write_storage()
+        .insert(
+            slider,
+            components::Joint::new(
+                post,
+                components::JointKind::Prismatic {
+                    axis: Vector3::x(),
+                    limits: Some((-3.0, 0.0)),
+                    motor: Some(components::JointMotor {
+                        target_velocity: -0.5,
+                        max_force: 50.0,
+                    }),
+                },
+            ),
+        )
+        .expect("The entity is alive");
+}
 
-    let mesh = {
-        let mesh_data: types::MeshData = Shape::Cube
-            .generate::<(Vec<Position>, Vec<Normal>, Vec<Tangent>, Vec<TexCoord>)>(Some((
-                1.0, 1.0, 1.0,
-            )))
-            .into();
+/// Creates a wind tunnel, a gravity well floating over the floor and a water
+/// pool where the bodies float.
+fn create_force_fields(world: &mut World) {
+    use components::{ForceField, ForceFieldKind, ForceVolume};
 
-        visual_utils::create_mesh(world, mesh_data)
+    let mut create = |position: Vector3<f32>, field: ForceField| {
+        let mut transform = Transform::default();
+        transform.set_translation(position);
+        world.create_entity().with(transform).with(field).build();
     };
 
-    let mut rng = rand::thread_rng();
-    let mat = visual_utils::create_material(
+    create(
+        Vector3::new(12.0, 2.0, 6.0),
+        ForceField::new(
+            ForceVolume::Box {
+                half_extents: Vector3::new(4.0, 2.0, 2.0),
+            },
+            ForceFieldKind::Wind {
+                force: Vector3::new(-40.0, 0.0, 0.0),
+            },
+        ),
+    );
+    create(
+        Vector3::new(0.0, 5.0, 10.0),
+        ForceField::new(
+            ForceVolume::Sphere { radius: 5.0 },
+            ForceFieldKind::GravityWell { strength: 15.0 },
+        ),
+    );
+    create(
+        Vector3::new(-12.0, 1.6, 12.0),
+        ForceField::new(
+            ForceVolume::Box {
+                half_extents: Vector3::new(3.0, 1.4, 3.0),
+            },
+            ForceFieldKind::Buoyancy {
+                density: 1.5,
+                drag: 2.0,
+            },
+        ),
+    );
+}
+
+/// Creates a humanoid ragdoll, described in RON, and the ragdoll of the
+/// `Monster.gltf` skeleton.
+///
+/// No animated scene is loaded, so the ragdolls stand in their rest pose until
+/// they are knocked out.
+fn create_ragdolls(world: &mut World) {
+    let assets = application_root_dir()
+        .expect("The application root is known")
+        .join("assets");
+
+    let humanoid = ragdoll::RagdollDesc::load(assets.join("ragdoll").join("humanoid.ron"))
+        .expect("Failed to load the ragdoll");
+    ragdoll::build_ragdoll(
         world,
-        LinSrgba::new(rng.gen(), rng.gen(), rng.gen(), 1.0),
-        0.0,
-        1.0,
+        &humanoid,
+        Vector3::new(-6.0, 0.2, -2.0),
+        LinSrgba::new(0.9, 0.75, 0.6, 1.0),
     );
 
+    match ragdoll::RagdollDesc::from_gltf(assets.join("mesh").join("Monster.gltf"), 0.05) {
+        Ok(monster) => {
+            ragdoll::build_ragdoll(
+                world,
+                &monster,
+                Vector3::new(-10.0, 0.2, -2.0),
+                LinSrgba::new(0.4, 0.7, 0.3, 1.0),
+            );
+        }
+        Err(e) => log::error!("Failed to build the Monster ragdoll: {}", e),
+    }
+}
+
+/// Creates the spawner that drops a cube each 10 sec, at 6 meters of height.
+fn create_cube_spawner(world: &mut World) {
     let mut transf = Transform::default();
-    transf.set_translation(pos);
+    transf.set_translation_y(6.0);
 
     world
         .create_entity()
-        .with(mesh)
-        .with(mat)
-        .with(BoundingSphere::origin(1.0))
         .with(transf)
-        .with(shape)
-        .with(rb)
+        .with(
+            components::Spawner::new(
+                10.0,
+                components::SpawnShape::Cube {
+                    half_extents: Vector3::new(1.0, 1.0, 1.0),
+                },
+            )
+            .with_volume(Vector3::new(5.0, 0.0, 5.0))
+            .with_pooling(),
+        )
+        .build();
+}
+
+/// Creates a fountain that shoots a sphere each half second; the spheres live
+/// 8 seconds, and at most 12 of them exist at once.
+///
+/// The spawner is seeded, so the spawn sequence is the same on each run.
+fn create_sphere_fountain(world: &mut World) {
+    let mut transf = Transform::default();
+    transf.set_translation_xyz(-12.0, 1.0, -12.0);
+
+    world
+        .create_entity()
+        .with(transf)
+        .with(
+            components::Spawner::new(0.5, components::SpawnShape::Sphere { radius: 0.3 })
+                .with_volume(Vector3::new(0.3, 0.0, 0.3))
+                .with_initial_velocity(Vector3::new(1.0, 9.0, 1.0))
+                .with_max_alive(12)
+                .with_lifetime(8.0)
+                .with_seed(42),
+        )
         .build();
 }
 
@@ -254,56 +526,29 @@ fn add_cube_entity(world: &mut World, pos: Vector3<f32>) {
 /// 1. The character (With RigidBody).
 /// 2. The camera boom handle attached to the character.
 /// 3. The camera attached to the camera bool handle.
-fn create_character_entity(world: &mut World) {
+fn create_character_entity(world: &mut World, position: Vector3<f32>) {
     let character = {
-        let shape = {
-            let desc = ShapeDesc::Capsule {
-                half_height: 1.0,
-                radius: 0.5,
-            };
-            let physics_world = world.fetch::<PhysicsWorld<f32>>();
-            physics_world.shape_server().create(&desc)
-        };
-
-        let rb = {
-            let mut rb_desc = RigidBodyDesc::default();
-            rb_desc.lock_rotation_x = true;
-            rb_desc.lock_rotation_y = true;
-            rb_desc.lock_rotation_z = true;
-            rb_desc.contacts_to_report = 3;
-            rb_desc.friction = 0.0;
-            rb_desc.bounciness = 0.0;
-
-            let physics_world = world.fetch::<PhysicsWorld<f32>>();
-            physics_world.rigid_body_server().create(&rb_desc)
-        };
-
-        let mesh = {
-            let mesh_data: types::MeshData = Shape::Cube
-                .generate::<(Vec<Position>, Vec<Normal>, Vec<Tangent>, Vec<TexCoord>)>(Some((
-                    0.5, 1.5, 0.5,
-                )))
-                .into();
-
-            visual_utils::create_mesh(world, mesh_data)
-        };
-
-        let mat =
-            visual_utils::create_material(world, LinSrgba::new(0.65, 1.0, 0.90, 1.0), 0.0, 1.0);
-
-        let mut transf = Transform::default();
-        transf.set_translation(Vector3::new(-3.0, 2.0, -3.0));
-
-        world
+        let mut rb_desc = RigidBodyDesc::default();
+        rb_desc.lock_rotation_x = true;
+        rb_desc.lock_rotation_y = true;
+        rb_desc.lock_rotation_z = true;
+        rb_desc.contacts_to_report = 6;
+        rb_desc.friction = 0.0;
+        rb_desc.bounciness = 0.0;
+
+        let character = world
             .create_entity()
-            .with(mesh)
-            .with(mat)
-            .with(BoundingSphere::origin(1.0))
-            .with(transf)
-            .with(shape)
-            .with(rb)
             .with(components::CharacterBody)
-            .build()
+            .build();
+
+        PhysicsEntityBuilder::new()
+            .capsule(1.0, 0.5)
+            .rigid_body(rb_desc)
+            .color(LinSrgba::new(0.65, 1.0, 0.90, 1.0))
+            .at(position)
+            .build_into(world, character);
+
+        character
     };
 
     let camera_boom_handle = {
@@ -0,0 +1,213 @@
+use std::path::Path;
+
+use amethyst::{
+    core::math::{Matrix4, Point3, Vector3},
+    ecs::Entity,
+    prelude::*,
+    renderer::palette::LinSrgba,
+    Error,
+};
+use amethyst_physics::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    convex_decomposition::{self, ConvexDecomposition},
+    entity_builder::PhysicsEntityBuilder,
+    visual_utils,
+};
+
+/// How the collider is built from the mesh triangles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MeshColliderKind {
+    /// Convex hull of the mesh points, meant for the dynamic props.
+    ConvexHull,
+    /// The exact triangles, meant for the static level geometry.
+    TriMesh,
+    /// Compound of convex pieces, for the concave dynamic props.
+    ConvexDecomposition,
+}
+
+/// Triangles of a mesh asset, with all its node transforms already applied.
+#[derive(Debug, Clone, Default)]
+pub struct MeshGeometry {
+    pub points: Vec<Point3<f32>>,
+    pub indices: Vec<Point3<usize>>,
+}
+
+impl MeshGeometry {
+    /// Loads a `.gltf`, `.glb` or `.obj` file, depending on its extension.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gltf") | Some("glb") => Self::load_gltf(path),
+            Some("obj") => Self::load_obj(path),
+            _ => Err(Error::from_string(format!(
+                "Unsupported mesh format: {}",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Loads the triangles of all the meshes of the default scene.
+    pub fn load_gltf(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let (document, buffers, _) = gltf::import(path)?;
+
+        let mut geometry = MeshGeometry::default();
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .ok_or_else(|| Error::from_string("The glTF file has no scene"))?;
+
+        for node in scene.nodes() {
+            geometry.add_gltf_node(&node, &Matrix4::identity(), &buffers);
+        }
+        Ok(geometry)
+    }
+
+    fn add_gltf_node(
+        &mut self,
+        node: &gltf::Node<'_>,
+        parent_transform: &Matrix4<f32>,
+        buffers: &[gltf::buffer::Data],
+    ) {
+        let transform = parent_transform * Matrix4::from(node.transform().matrix());
+
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    continue;
+                }
+
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let positions = match reader.read_positions() {
+                    Some(positions) => positions,
+                    None => continue,
+                };
+
+                let offset = self.points.len();
+                self.points.extend(
+                    positions.map(|p| transform.transform_point(&Point3::new(p[0], p[1], p[2]))),
+                );
+                let count = self.points.len() - offset;
+
+                let indices: Vec<usize> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+                    None => (0..count).collect(),
+                };
+                self.indices.extend(
+                    indices
+                        .chunks_exact(3)
+                        .map(|t| Point3::new(t[0] + offset, t[1] + offset, t[2] + offset)),
+                );
+            }
+        }
+
+        for child in node.children() {
+            self.add_gltf_node(&child, &transform, buffers);
+        }
+    }
+
+    /// Loads the triangles of all the objects of the file.
+    pub fn load_obj(path: impl AsRef<Path>) -> Result<Self, Error> {
+        use wavefront_obj::obj;
+
+        let source = std::fs::read_to_string(path)?;
+        let obj_set = obj::parse(source)
+            .map_err(|e| Error::from_string(format!("Failed to parse the OBJ: {:?}", e)))?;
+
+        let mut geometry = MeshGeometry::default();
+        for object in &obj_set.objects {
+            let offset = geometry.points.len();
+            geometry.points.extend(
+                object
+                    .vertices
+                    .iter()
+                    .map(|v| Point3::new(v.x as f32, v.y as f32, v.z as f32)),
+            );
+
+            for shape in object.geometry.iter().flat_map(|g| g.shapes.iter()) {
+                if let obj::Primitive::Triangle(a, b, c) = shape.primitive {
+                    geometry
+                        .indices
+                        .push(Point3::new(a.0 + offset, b.0 + offset, c.0 + offset));
+                }
+            }
+        }
+        Ok(geometry)
+    }
+
+    pub fn scale(&mut self, scale: &Vector3<f32>) {
+        for p in &mut self.points {
+            p.coords.component_mul_assign(scale);
+        }
+    }
+
+    pub fn convex_hull_desc(&self) -> ShapeDesc<f32> {
+        ShapeDesc::Convex {
+            points: self.points.clone(),
+        }
+    }
+
+    pub fn trimesh_desc(&self) -> ShapeDesc<f32> {
+        ShapeDesc::TriMesh {
+            points: self.points.clone(),
+            indices: self.indices.clone(),
+        }
+    }
+}
+
+/// Creates an entity with the collider and the visual built from the same mesh
+/// file.
+///
+/// The triangle meshes are always static, since they can't be simulated as
+/// dynamic bodies.
+/// The convex decomposition is cached next to the mesh file.
+pub fn create_mesh_entity(
+    world: &mut World,
+    path: impl AsRef<Path>,
+    kind: MeshColliderKind,
+    mode: BodyMode,
+    position: Vector3<f32>,
+    scale: f32,
+    color: LinSrgba,
+) -> Result<Entity, Error> {
+    let path = path.as_ref();
+    let mut geometry = MeshGeometry::load(path)?;
+
+    // The decomposition is cached for the unscaled mesh.
+    let decomposition = if kind == MeshColliderKind::ConvexDecomposition {
+        Some(ConvexDecomposition::load_or_compute(
+            path,
+            &geometry,
+            convex_decomposition::DEFAULT_ERROR,
+            convex_decomposition::DEFAULT_MIN_COMPONENTS,
+        ))
+    } else {
+        None
+    };
+    geometry.scale(&Vector3::repeat(scale));
+
+    let shape_desc = match decomposition {
+        Some(decomposition) => decomposition.shape_desc(scale),
+        None if kind == MeshColliderKind::TriMesh => geometry.trimesh_desc(),
+        None => geometry.convex_hull_desc(),
+    };
+
+    let mode = match kind {
+        MeshColliderKind::ConvexHull | MeshColliderKind::ConvexDecomposition => mode,
+        MeshColliderKind::TriMesh => BodyMode::Static,
+    };
+
+    let mesh = visual_utils::create_mesh(
+        world,
+        visual_utils::mesh_data_from_triangles(&geometry.points, &geometry.indices),
+    );
+
+    Ok(PhysicsEntityBuilder::new()
+        .shape(shape_desc)
+        .mode(mode)
+        .at(position)
+        .mesh(mesh)
+        .color(color)
+        .build(world))
+}
@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet};
+
+use amethyst::{
+    controls::HideCursor,
+    core::{
+        math::{Point2, Point3, Vector2},
+        Transform,
+    },
+    ecs::prelude::*,
+    input::{InputHandler, StringBindings},
+    renderer::{ActiveCamera, Camera},
+    ui::{UiFinder, UiText},
+    window::ScreenDimensions,
+};
+use amethyst_physics::prelude::*;
+use ncollide3d::{
+    query::Ray,
+    shape::{Ball, Capsule, Compound, ConvexHull, Cuboid, ShapeHandle, TriMesh},
+};
+
+use crate::{
+    components::CharacterBody,
+    resources::{MousePicking, PickingRay, RaycastHit, ShapeRegistry},
+};
+
+/// Rays longer than this don't hit anything.
+const MAX_PICKING_DISTANCE: f32 = 500.0;
+/// Points on each cap of the hull that approximates a cylinder.
+const CYLINDER_SEGMENTS: usize = 16;
+
+/// Casts a ray from the active camera through the cursor, and stores the
+/// closest body hit in the `MousePicking` resource.
+///
+/// While the cursor is hidden the ray passes through the screen center.
+/// The character and the disabled bodies, like the pooled ones, are never picked.
+///
+/// The physics servers don't expose a ray cast, so each body is tested against
+/// a collider built from the description stored in the `ShapeRegistry`.
+/// The bodies whose shape was created without the registry can't be picked.
+#[derive(Default)]
+pub struct MousePickingSystem {
+    /// Ray cast colliders, built the first time each shape is tested.
+    colliders: HashMap<PhysicsShapeTag, Option<ShapeHandle<f32>>>,
+    /// Shapes tested by the last run; the colliders of the others are dropped.
+    tested: HashSet<PhysicsShapeTag>,
+}
+
+impl<'s> System<'s> for MousePickingSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'s>,
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        Read<'s, ShapeRegistry>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, HideCursor>,
+        Read<'s, ActiveCamera>,
+        ReadExpect<'s, ScreenDimensions>,
+        Write<'s, MousePicking>,
+        ReadStorage<'s, Camera>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, PhysicsHandle<PhysicsShapeTag>>,
+        ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
+        ReadStorage<'s, CharacterBody>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            physics_world,
+            shape_registry,
+            input,
+            hide_cursor,
+            active_camera,
+            screen_dimensions,
+            mut picking,
+            cameras,
+            transforms,
+            shapes,
+            bodies,
+            characters,
+        ): Self::SystemData,
+    ) {
+        picking.ray = None;
+        picking.hit = None;
+
+        let camera = active_camera
+            .entity
+            .and_then(|e| Some((cameras.get(e)?, transforms.get(e)?)))
+            .or_else(|| (&cameras, &transforms).join().next());
+        let (camera, camera_transform) = match camera {
+            Some(camera) => camera,
+            None => return,
+        };
+
+        let screen_size = Vector2::new(screen_dimensions.width(), screen_dimensions.height());
+        let screen_position = if hide_cursor.hide {
+            Point2::from(screen_size * 0.5)
+        } else {
+            match input.mouse_position() {
+                Some((x, y)) => Point2::new(x, y),
+                None => return,
+            }
+        };
+
+        let camera_ray =
+            camera
+                .projection()
+                .screen_ray(screen_position, screen_size, camera_transform);
+        let ray = Ray::new(camera_ray.origin, camera_ray.direction.normalize());
+        picking.ray = Some(PickingRay {
+            screen_position,
+            origin: ray.origin,
+            direction: ray.dir,
+        });
+
+        let rb_server = physics_world.rigid_body_server();
+        self.tested.clear();
+        for (entity, shape, body, _) in (&entities, &shapes, &bodies, !&characters).join() {
+            if rb_server.mode(body.get()) == BodyMode::Disabled {
+                continue;
+            }
+
+            self.tested.insert(shape.get());
+            let collider = self.colliders.entry(shape.get()).or_insert_with(|| {
+                shape_registry
+                    .desc(shape.get())
+                    .and_then(collider_from_desc)
+            });
+            let ray_cast = match collider.as_ref().and_then(|c| c.as_ray_cast()) {
+                Some(ray_cast) => ray_cast,
+                None => continue,
+            };
+
+            let pose = rb_server.transform(body.get());
+            let toi = match ray_cast.toi_with_ray(&pose, &ray, true) {
+                Some(toi) => toi,
+                None => continue,
+            };
+
+            let is_closer = picking.hit.as_ref().map_or(true, |hit| toi < hit.distance);
+            if toi <= MAX_PICKING_DISTANCE && is_closer {
+                picking.hit = Some(RaycastHit {
+                    entity,
+                    point: ray.point_at(toi),
+                    distance: toi,
+                });
+            }
+        }
+
+        // Forget the colliders of the shapes not used anymore.
+        let tested = &self.tested;
+        self.colliders.retain(|tag, _| tested.contains(tag));
+    }
+}
+
+/// Builds the ray cast collider of a shape description.
+///
+/// Returns `None` when the shape can't be built, like a convex hull of
+/// degenerate points.
+fn collider_from_desc(desc: &ShapeDesc<f32>) -> Option<ShapeHandle<f32>> {
+    Some(match desc {
+        ShapeDesc::Sphere { radius } => ShapeHandle::new(Ball::new(*radius)),
+        ShapeDesc::Cube { half_extents } => ShapeHandle::new(Cuboid::new(*half_extents)),
+        ShapeDesc::Capsule {
+            half_height,
+            radius,
+        } => ShapeHandle::new(Capsule::new(*half_height, *radius)),
+        ShapeDesc::Cylinder {
+            half_height,
+            radius,
+        } => ShapeHandle::new(cylinder_hull(*half_height, *radius)?),
+        ShapeDesc::Convex { points } => ShapeHandle::new(ConvexHull::try_from_points(points)?),
+        ShapeDesc::TriMesh { points, indices } => {
+            ShapeHandle::new(TriMesh::new(points.clone(), indices.clone(), None))
+        }
+        ShapeDesc::Compound { shapes } => ShapeHandle::new(Compound::new(
+            shapes
+                .iter()
+                .filter_map(|(offset, part)| Some((*offset, collider_from_desc(part)?)))
+                .collect(),
+        )),
+        _ => return None,
+    })
+}
+
+/// Approximates the cylinder with the hull of the points around its caps,
+/// since the ncollide cylinder can't be ray cast.
+fn cylinder_hull(half_height: f32, radius: f32) -> Option<ConvexHull<f32>> {
+    let points: Vec<Point3<f32>> = (0..CYLINDER_SEGMENTS)
+        .flat_map(|i| {
+            let angle = i as f32 / CYLINDER_SEGMENTS as f32 * std::f32::consts::PI * 2.0;
+            let (x, z) = (angle.cos() * radius, angle.sin() * radius);
+            vec![
+                Point3::new(x, half_height, z),
+                Point3::new(x, -half_height, z),
+            ]
+        })
+        .collect();
+    ConvexHull::try_from_points(&points)
+}
+
+/// Shows the picking ray and the picked body in the `mouse_raycast` UI.
+#[derive(Debug, Default)]
+pub struct MousePickingUiSystem {
+    mouse_position_label: Option<Entity>,
+    under_mouse_label: Option<Entity>,
+}
+
+impl<'s> System<'s> for MousePickingUiSystem {
+    type SystemData = (
+        UiFinder<'s>,
+        Read<'s, MousePicking>,
+        WriteStorage<'s, UiText>,
+    );
+
+    fn run(&mut self, (finder, picking, mut ui_texts): Self::SystemData) {
+        if self.mouse_position_label.is_none() {
+            self.mouse_position_label = finder.find("mouse_position");
+        }
+        if self.under_mouse_label.is_none() {
+            self.under_mouse_label = finder.find("under_mouse");
+        }
+
+        if let Some(text) = self
+            .mouse_position_label
+            .and_then(|label| ui_texts.get_mut(label))
+        {
+            text.text = match &picking.ray {
+                Some(ray) => format!(
+                    "({:.0}, {:.0})",
+                    ray.screen_position.x, ray.screen_position.y
+                ),
+                None => String::from("N/A"),
+            };
+        }
+
+        if let Some(text) = self
+            .under_mouse_label
+            .and_then(|label| ui_texts.get_mut(label))
+        {
+            text.text = match &picking.hit {
+                Some(hit) => format!(
+                    "Entity {} at ({:.1}, {:.1}, {:.1}), {:.1}m",
+                    hit.entity.id(),
+                    hit.point.x,
+                    hit.point.y,
+                    hit.point.z,
+                    hit.distance
+                ),
+                None => String::from("Nothing"),
+            };
+        }
+    }
+}
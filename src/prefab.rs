@@ -0,0 +1,117 @@
+use amethyst::{
+    assets::{PrefabData, ProgressCounter},
+    core::Transform,
+    derive::PrefabData,
+    ecs::prelude::*,
+    renderer::{
+        formats::GraphicsPrefab,
+        rendy::mesh::{Normal, Position, Tangent, TexCoord},
+    },
+    Error,
+};
+use amethyst_physics::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::Mass,
+    resources::ShapeRegistry,
+    scene::{SceneBodyMode, SceneShape},
+};
+
+/// Creates the collider of the entity; the shape is taken from the `ShapeRegistry`.
+impl<'a> PrefabData<'a> for SceneShape {
+    type SystemData = (
+        ReadExpect<'a, PhysicsWorld<f32>>,
+        Write<'a, ShapeRegistry>,
+        WriteStorage<'a, PhysicsHandle<PhysicsShapeTag>>,
+    );
+    type Result = ();
+
+    fn add_to_entity(
+        &self,
+        entity: Entity,
+        (physics_world, shape_registry, shapes): &mut Self::SystemData,
+        _: &[Entity],
+        _: &[Entity],
+    ) -> Result<(), Error> {
+        let shape = shape_registry.get_or_create(&physics_world, &self.shape_desc());
+        shapes.insert(entity, shape)?;
+        Ok(())
+    }
+}
+
+/// Rigid body declared in a prefab.
+///
+/// The omitted parameters take the `RigidBodyDesc` default.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RigidBodyPrefab {
+    pub mode: Option<SceneBodyMode>,
+    pub mass: Option<f32>,
+    pub friction: Option<f32>,
+    pub bounciness: Option<f32>,
+    pub lock_translation: (bool, bool, bool),
+    pub lock_rotation: (bool, bool, bool),
+    pub contacts_to_report: usize,
+}
+
+impl RigidBodyPrefab {
+    pub fn rigid_body_desc(&self) -> RigidBodyDesc<f32> {
+        let mut rb_desc = RigidBodyDesc::default();
+        if let Some(mode) = self.mode {
+            rb_desc.mode = mode.into();
+        }
+        if let Some(mass) = self.mass {
+            rb_desc.mass = mass;
+        }
+        if let Some(friction) = self.friction {
+            rb_desc.friction = friction;
+        }
+        if let Some(bounciness) = self.bounciness {
+            rb_desc.bounciness = bounciness;
+        }
+        rb_desc.lock_translation_x = self.lock_translation.0;
+        rb_desc.lock_translation_y = self.lock_translation.1;
+        rb_desc.lock_translation_z = self.lock_translation.2;
+        rb_desc.lock_rotation_x = self.lock_rotation.0;
+        rb_desc.lock_rotation_y = self.lock_rotation.1;
+        rb_desc.lock_rotation_z = self.lock_rotation.2;
+        rb_desc.contacts_to_report = self.contacts_to_report;
+        rb_desc
+    }
+}
+
+impl<'a> PrefabData<'a> for RigidBodyPrefab {
+    type SystemData = (
+        ReadExpect<'a, PhysicsWorld<f32>>,
+        WriteStorage<'a, PhysicsHandle<PhysicsRigidBodyTag>>,
+        WriteStorage<'a, Mass>,
+    );
+    type Result = ();
+
+    fn add_to_entity(
+        &self,
+        entity: Entity,
+        (physics_world, bodies, masses): &mut Self::SystemData,
+        _: &[Entity],
+        _: &[Entity],
+    ) -> Result<(), Error> {
+        let rb_desc = self.rigid_body_desc();
+        let rb = physics_world.rigid_body_server().create(&rb_desc);
+        bodies.insert(entity, rb)?;
+        masses.insert(entity, Mass(rb_desc.mass))?;
+        Ok(())
+    }
+}
+
+/// Prefab of the physics props: the render prefab with the optional `shape` and
+/// `body` fields.
+#[derive(Default, Deserialize, Serialize, PrefabData)]
+#[serde(default)]
+#[serde(deny_unknown_fields)]
+pub struct PhysicsPrefabData {
+    graphics: Option<GraphicsPrefab<(Vec<Position>, Vec<Normal>, Vec<Tangent>, Vec<TexCoord>)>>,
+    transform: Option<Transform>,
+    shape: Option<SceneShape>,
+    body: Option<RigidBodyPrefab>,
+}
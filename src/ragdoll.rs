@@ -0,0 +1,440 @@
+use std::{collections::HashMap, path::Path};
+
+use amethyst::{
+    core::{
+        math::{
+            Isometry3, Matrix3, Matrix4, Point3, Rotation3, Translation3, UnitQuaternion, Vector3,
+        },
+        Named, Transform,
+    },
+    ecs::prelude::*,
+    input::{InputEvent, StringBindings},
+    renderer::palette::LinSrgba,
+    shrev::EventChannel,
+    Error,
+};
+use amethyst_physics::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::{Joint, JointKind, Ragdoll, RagdollBone},
+    entity_builder::PhysicsEntityBuilder,
+};
+
+/// Ragdoll description, loaded from the RON files in `assets/ragdoll/` or
+/// extracted from the skin of a glTF file.
+///
+/// The bones are listed parents first, and positioned in the rest pose,
+/// relative to the ragdoll origin.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RagdollDesc {
+    pub bones: Vec<BoneDesc>,
+}
+
+/// Capsule going from `start` to `end`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BoneDesc {
+    pub name: String,
+    /// The bone is joined to its parent at `start`.
+    #[serde(default)]
+    pub parent: Option<String>,
+    pub start: [f32; 3],
+    pub end: [f32; 3],
+    pub radius: f32,
+    #[serde(default = "default_bone_mass")]
+    pub mass: f32,
+    #[serde(default)]
+    pub joint: BoneJointDesc,
+    /// Name of the animated node followed by the bone; by default the bone name.
+    #[serde(default)]
+    pub node: Option<String>,
+}
+
+fn default_bone_mass() -> f32 {
+    1.0
+}
+
+/// Joint with the parent bone; the angles are in degrees.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub enum BoneJointDesc {
+    Ball {
+        swing_limit: f32,
+    },
+    /// The axis is expressed in the rest pose.
+    Hinge {
+        axis: [f32; 3],
+        min: f32,
+        max: f32,
+    },
+}
+
+impl Default for BoneJointDesc {
+    fn default() -> Self {
+        BoneJointDesc::Ball { swing_limit: 45.0 }
+    }
+}
+
+impl RagdollDesc {
+    /// Builds a bone for each skin joint that has child joints, going from the
+    /// joint to the middle of its children.
+    ///
+    /// All the bones use ball joints; the radius is a fraction of the bone length.
+    pub fn from_gltf(path: impl AsRef<Path>, scale: f32) -> Result<Self, Error> {
+        let (document, _, _) = gltf::import(path)?;
+        let skin = document
+            .skins()
+            .next()
+            .ok_or_else(|| Error::from_string("The glTF file has no skin"))?;
+        let scene = document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+            .ok_or_else(|| Error::from_string("The glTF file has no scene"))?;
+
+        // Rest position and parent of each node.
+        let mut positions = HashMap::new();
+        let mut parents = HashMap::new();
+        for node in scene.nodes() {
+            collect_nodes(
+                &node,
+                None,
+                &Matrix4::identity(),
+                &mut positions,
+                &mut parents,
+            );
+        }
+
+        let joints: Vec<gltf::Node<'_>> = skin.joints().collect();
+        let is_joint = |index: usize| joints.iter().any(|j| j.index() == index);
+        let node_name = |node: &gltf::Node<'_>| {
+            node.name()
+                .map(String::from)
+                .unwrap_or_else(|| format!("node_{}", node.index()))
+        };
+
+        let mut desc = RagdollDesc::default();
+        for joint in &joints {
+            let children: Vec<usize> = joint
+                .children()
+                .map(|c| c.index())
+                .filter(|c| is_joint(*c))
+                .collect();
+            if children.is_empty() {
+                continue;
+            }
+
+            let start = positions[&joint.index()] * scale;
+            let end = children
+                .iter()
+                .fold(Vector3::zeros(), |sum, c| sum + positions[c].coords)
+                * (scale / children.len() as f32);
+            let length = (end - start.coords).norm();
+            if length <= std::f32::EPSILON {
+                continue;
+            }
+
+            // The closest ancestor that is a skin joint.
+            let mut parent = parents.get(&joint.index()).copied();
+            while let Some(p) = parent {
+                if is_joint(p) {
+                    break;
+                }
+                parent = parents.get(&p).copied();
+            }
+            let parent = parent.and_then(|p| joints.iter().find(|j| j.index() == p));
+
+            desc.bones.push(BoneDesc {
+                name: node_name(joint),
+                parent: parent.map(node_name),
+                start: start.coords.into(),
+                end: end.into(),
+                radius: length * 0.2,
+                mass: default_bone_mass(),
+                joint: BoneJointDesc::default(),
+                node: None,
+            });
+        }
+
+        // The bones of the joints without children are not created, so their
+        // children are attached to the closest created bone.
+        let names: Vec<String> = desc.bones.iter().map(|b| b.name.clone()).collect();
+        for bone in &mut desc.bones {
+            if bone.parent.as_ref().map_or(false, |p| !names.contains(p)) {
+                bone.parent = None;
+            }
+        }
+        Ok(desc)
+    }
+}
+
+fn collect_nodes(
+    node: &gltf::Node<'_>,
+    parent: Option<usize>,
+    parent_transform: &Matrix4<f32>,
+    positions: &mut HashMap<usize, Point3<f32>>,
+    parents: &mut HashMap<usize, usize>,
+) {
+    let transform = parent_transform * Matrix4::from(node.transform().matrix());
+    positions.insert(node.index(), transform.transform_point(&Point3::origin()));
+    if let Some(parent) = parent {
+        parents.insert(node.index(), parent);
+    }
+    for child in node.children() {
+        collect_nodes(&child, Some(node.index()), &transform, positions, parents);
+    }
+}
+
+impl BoneDesc {
+    /// Pose of the capsule, relative to the ragdoll origin; the capsule axis is Y.
+    fn pose(&self) -> Isometry3<f32> {
+        let start = Vector3::from(self.start);
+        let end = Vector3::from(self.end);
+        let rotation = UnitQuaternion::rotation_between(&Vector3::y(), &(end - start))
+            .unwrap_or_else(|| {
+                UnitQuaternion::from_axis_angle(&Vector3::x_axis(), std::f32::consts::PI)
+            });
+        Isometry3::from_parts(Translation3::from((start + end) * 0.5), rotation)
+    }
+
+    fn length(&self) -> f32 {
+        (Vector3::from(self.end) - Vector3::from(self.start)).norm()
+    }
+}
+
+/// Creates the ragdoll entity, with a capsule body for each bone and a joint
+/// between each bone and its parent.
+///
+/// The ragdoll starts inactive: its bodies are kinematic, and follow the
+/// animated nodes, or the rest pose, until `Ragdoll::active` is set.
+/// The nodes are the entities `Named` as the bones, so the animated scene must
+/// be loaded before the ragdoll is built; the demo loads none, so its ragdolls
+/// follow the rest pose.
+/// The capsules are a bit shorter than the bones, so the bodies joined
+/// together don't overlap.
+pub fn build_ragdoll(
+    world: &mut World,
+    desc: &RagdollDesc,
+    position: Vector3<f32>,
+    color: LinSrgba,
+) -> Entity {
+    let mut transform = Transform::default();
+    transform.set_translation(position);
+    let root = world.create_entity().with(transform).build();
+    let origin = Isometry3::translation(position.x, position.y, position.z);
+    let nodes: HashMap<String, Entity> = (&world.entities(), &world.read_storage::<Named>())
+        .join()
+        .map(|(entity, name)| (name.name.to_string(), entity))
+        .collect();
+
+    let mut ragdoll = Ragdoll::default();
+    let mut entities = HashMap::new();
+    for bone in &desc.bones {
+        let rest = bone.pose();
+        let pose = origin * rest;
+
+        let mut rb_desc = RigidBodyDesc::default();
+        rb_desc.mode = BodyMode::Kinematic;
+        rb_desc.mass = bone.mass;
+
+        let entity = PhysicsEntityBuilder::new()
+            .capsule(
+                (bone.length() * 0.5 - bone.radius * 1.5).max(0.01),
+                bone.radius,
+            )
+            .rigid_body(rb_desc)
+            .at(pose.translation.vector)
+            .rotated(pose.rotation)
+            .interpolate(true)
+            .color(color)
+            .build(world);
+
+        if let Some(parent) = bone.parent.as_ref().and_then(|p| entities.get(p)) {
+            let anchor = Isometry3::translation(0.0, -bone.length() * 0.5, 0.0);
+            let kind = match bone.joint {
+                BoneJointDesc::Ball { swing_limit } => JointKind::Ball {
+                    swing_limit: Some(swing_limit.to_radians()),
+                },
+                BoneJointDesc::Hinge { axis, min, max } => JointKind::Hinge {
+                    axis: rest.rotation.inverse() * Vector3::from(axis),
+                    limits: Some((min.to_radians(), max.to_radians())),
+                    motor: None,
+                },
+            };
+            world
+                .write_storage()
+                .insert(entity, Joint::new(*parent, kind).with_anchor(anchor))
+                .expect("The entity is alive");
+        }
+
+        entities.insert(bone.name.clone(), entity);
+        ragdoll.bones.push(RagdollBone {
+            entity,
+            node: nodes.get(bone.node.as_ref().unwrap_or(&bone.name)).copied(),
+            rest,
+            node_offset: None,
+        });
+    }
+
+    world
+        .write_storage()
+        .insert(root, ragdoll)
+        .expect("The entity is alive");
+    root
+}
+
+/// Drives the ragdoll bodies.
+///
+/// While the ragdoll is inactive, its kinematic bodies follow the animated
+/// nodes, or the rest pose relative to the ragdoll entity.
+/// When it's activated the bodies become dynamic, keeping the velocity of the
+/// animation, so the ragdoll takes over from the animated pose.
+#[derive(Debug, Default)]
+pub struct RagdollSystem {
+    /// Whether each ragdoll was active during the last run.
+    was_active: HashMap<Entity, bool>,
+}
+
+impl<'s> System<'s> for RagdollSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'s>,
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        ReadExpect<'s, PhysicsTime>,
+        WriteStorage<'s, Ragdoll>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, physics_world, physics_time, mut ragdolls, transforms, rigid_body_tags): Self::SystemData,
+    ) {
+        let rb_server = physics_world.rigid_body_server();
+        self.was_active.retain(|e, _| entities.is_alive(*e));
+
+        for (entity, ragdoll, root_transform) in (&entities, &mut ragdolls, &transforms).join() {
+            let was_active = self.was_active.insert(entity, ragdoll.active);
+            if ragdoll.active {
+                if was_active != Some(true) {
+                    for bone in &ragdoll.bones {
+                        if let Some(body) = rigid_body_tags.get(bone.entity) {
+                            rb_server.set_mode(body.get(), BodyMode::Dynamic);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            if was_active == Some(true) {
+                // The ragdoll was deactivated; the animation takes the bodies back.
+                for bone in &mut ragdoll.bones {
+                    bone.node_offset = None;
+                    if let Some(body) = rigid_body_tags.get(bone.entity) {
+                        rb_server.set_mode(body.get(), BodyMode::Kinematic);
+                    }
+                }
+            }
+
+            let root_pose = root_transform.isometry();
+            for bone in &mut ragdoll.bones {
+                let body = match rigid_body_tags.get(bone.entity) {
+                    Some(body) => body.get(),
+                    None => continue,
+                };
+
+                let node_pose = bone
+                    .node
+                    .and_then(|node| transforms.get(node))
+                    .map(global_isometry);
+                let rest = bone.rest;
+                let target = match node_pose {
+                    Some(node_pose) => {
+                        let offset = *bone
+                            .node_offset
+                            .get_or_insert_with(|| node_pose.inverse() * root_pose * rest);
+                        node_pose * offset
+                    }
+                    None => root_pose * rest,
+                };
+
+                // The kinematic velocity is kept, so the bodies don't stop when
+                // the ragdoll is activated.
+                let current = rb_server.transform(body);
+                let delta = physics_time.delta_seconds().max(std::f32::EPSILON);
+                rb_server.set_linear_velocity(
+                    body,
+                    &((target.translation.vector - current.translation.vector) / delta),
+                );
+                rb_server.set_transform(body, &target);
+            }
+        }
+    }
+}
+
+/// Rigid part of the global matrix, with the scale removed.
+fn global_isometry(transform: &Transform) -> Isometry3<f32> {
+    let m = transform.global_matrix();
+    let axis = |i: usize| Vector3::new(m[(0, i)], m[(1, i)], m[(2, i)]).normalize();
+    let rotation =
+        Rotation3::from_matrix_unchecked(Matrix3::from_columns(&[axis(0), axis(1), axis(2)]));
+    Isometry3::from_parts(
+        Translation3::new(m[(0, 3)], m[(1, 3)], m[(2, 3)]),
+        UnitQuaternion::from_rotation_matrix(&rotation),
+    )
+}
+
+/// Toggles all the ragdolls with the `Knockout` action.
+#[derive(Debug, Default)]
+pub struct RagdollKnockoutSystem {
+    input_event_reader: Option<ReaderId<InputEvent<StringBindings>>>,
+}
+
+impl<'s> System<'s> for RagdollKnockoutSystem {
+    type SystemData = (
+        ReadExpect<'s, EventChannel<InputEvent<StringBindings>>>,
+        WriteStorage<'s, Ragdoll>,
+    );
+
+    fn run(&mut self, (input_event_channel, mut ragdolls): Self::SystemData) {
+        let mut toggle = false;
+        for e in input_event_channel.read(self.input_event_reader.as_mut().unwrap()) {
+            if let InputEvent::ActionPressed(action) = e {
+                if action == "Knockout" {
+                    toggle = !toggle;
+                }
+            }
+        }
+
+        if toggle {
+            for ragdoll in (&mut ragdolls).join() {
+                ragdoll.active = !ragdoll.active;
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        let mut ie = world.fetch_mut::<EventChannel<InputEvent<StringBindings>>>();
+        self.input_event_reader = Some(ie.register_reader());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amethyst::config::Config;
+
+    use super::*;
+
+    #[test]
+    fn humanoid_ragdoll_loads() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/ragdoll/humanoid.ron");
+        let desc = RagdollDesc::load(path).expect("The ragdoll is valid");
+
+        assert_eq!(desc.bones.len(), 11);
+        assert!(desc.bones[0].parent.is_none());
+        // The parents are listed first.
+        for (i, bone) in desc.bones.iter().enumerate().skip(1) {
+            let parent = bone.parent.as_ref().expect("The bone has a parent");
+            assert!(desc.bones[..i].iter().any(|b| &b.name == parent));
+        }
+    }
+}
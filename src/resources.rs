@@ -0,0 +1,163 @@
+use std::collections::{HashMap, HashSet};
+
+use amethyst::{
+    core::math::{Point2, Point3, Vector3},
+    ecs::Entity,
+};
+use amethyst_physics::prelude::*;
+
+/// Volume where the bodies are allowed to live; the bodies that leave it are
+/// deleted by the `DespawnSystem`, while the character is respawned.
+#[derive(Debug, Clone, Copy)]
+pub struct KillVolume {
+    /// Bodies below this height are deleted.
+    pub min_y: f32,
+    /// Half size of the area, centered on the origin, on the X and Z axes.
+    pub half_extent: f32,
+}
+
+impl KillVolume {
+    pub fn contains(&self, position: &Vector3<f32>) -> bool {
+        position.y >= self.min_y
+            && position.x.abs() <= self.half_extent
+            && position.z.abs() <= self.half_extent
+    }
+}
+
+impl Default for KillVolume {
+    fn default() -> Self {
+        KillVolume {
+            min_y: -20.0,
+            half_extent: 100.0,
+        }
+    }
+}
+
+/// How the character pushes the dynamic bodies it walks into.
+#[derive(Debug, Clone, Copy)]
+pub struct CharacterPushConfig {
+    /// Scales the speed given to the pushed body.
+    pub strength: f32,
+    /// Slower approaches don't push.
+    pub min_approach_speed: f32,
+    /// Heavier bodies can't be pushed.
+    pub max_mass: f32,
+}
+
+impl Default for CharacterPushConfig {
+    fn default() -> Self {
+        CharacterPushConfig {
+            strength: 1.5,
+            min_approach_speed: 0.1,
+            max_mass: 50.0,
+        }
+    }
+}
+
+/// Identifies an analytic shape; the sizes are stored as bits so they can be hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ShapeKey {
+    Sphere { radius: u32 },
+    Cube { half_extents: [u32; 3] },
+    Capsule { half_height: u32, radius: u32 },
+}
+
+impl ShapeKey {
+    /// Returns `None` for the shapes that are not worth to share, like the meshes.
+    fn new(desc: &ShapeDesc<f32>) -> Option<Self> {
+        match desc {
+            ShapeDesc::Sphere { radius } => Some(ShapeKey::Sphere {
+                radius: radius.to_bits(),
+            }),
+            ShapeDesc::Cube { half_extents } => Some(ShapeKey::Cube {
+                half_extents: [
+                    half_extents.x.to_bits(),
+                    half_extents.y.to_bits(),
+                    half_extents.z.to_bits(),
+                ],
+            }),
+            ShapeDesc::Capsule {
+                half_height,
+                radius,
+            } => Some(ShapeKey::Capsule {
+                half_height: half_height.to_bits(),
+                radius: radius.to_bits(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Registry of the physics shapes, so the colliders with the same `ShapeDesc`
+/// share a single shape.
+///
+/// The shape handles are reference counted, so the same handle can be assigned
+/// to many rigid bodies.
+/// It also remembers the description of each created shape, so it can be drawn
+/// by the debug overlay; the descriptions of the shapes no longer used are
+/// dropped by `retain_used`.
+#[derive(Default)]
+pub struct ShapeRegistry {
+    shapes: HashMap<ShapeKey, PhysicsHandle<PhysicsShapeTag>>,
+    descs: HashMap<PhysicsShapeTag, ShapeDesc<f32>>,
+}
+
+impl ShapeRegistry {
+    /// Returns the registered shape matching this description, creating it the
+    /// first time.
+    pub fn get_or_create(
+        &mut self,
+        physics_world: &PhysicsWorld<f32>,
+        desc: &ShapeDesc<f32>,
+    ) -> PhysicsHandle<PhysicsShapeTag> {
+        if let Some(shape) = ShapeKey::new(desc).and_then(|key| self.shapes.get(&key)) {
+            return shape.clone();
+        }
+
+        let shape = physics_world.shape_server().create(desc);
+        self.descs.insert(shape.get(), desc.clone());
+        if let Some(key) = ShapeKey::new(desc) {
+            self.shapes.insert(key, shape.clone());
+        }
+        shape
+    }
+
+    /// Description of a shape created through this registry.
+    pub fn desc(&self, shape: PhysicsShapeTag) -> Option<&ShapeDesc<f32>> {
+        self.descs.get(&shape)
+    }
+
+    /// Drops the descriptions of the shapes that are neither in `used` nor
+    /// shared by this registry.
+    pub fn retain_used(&mut self, used: &HashSet<PhysicsShapeTag>) {
+        let shared: HashSet<PhysicsShapeTag> = self.shapes.values().map(|s| s.get()).collect();
+        self.descs
+            .retain(|tag, _| used.contains(tag) || shared.contains(tag));
+    }
+}
+
+/// Result of the `MousePickingSystem`, updated each frame.
+#[derive(Debug, Clone, Default)]
+pub struct MousePicking {
+    /// `None` when there is no camera, or the cursor is outside the window.
+    pub ray: Option<PickingRay>,
+    /// Closest body hit by the ray.
+    pub hit: Option<RaycastHit>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PickingRay {
+    /// Cursor position, in pixels.
+    pub screen_position: Point2<f32>,
+    pub origin: Point3<f32>,
+    /// Normalized.
+    pub direction: Vector3<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastHit {
+    pub entity: Entity,
+    pub point: Point3<f32>,
+    /// Distance from the ray origin.
+    pub distance: f32,
+}
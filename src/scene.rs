@@ -0,0 +1,333 @@
+use amethyst::{
+    config::Config,
+    core::{
+        math::{Isometry3, Translation3, UnitQuaternion, Vector3},
+        Transform,
+    },
+    ecs::Entity,
+    prelude::*,
+    renderer::{
+        light,
+        palette::{LinSrgba, Srgb},
+    },
+    utils::application_root_dir,
+    Error,
+};
+use amethyst_physics::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::{KillZone, NotPushable, RespawnPoint},
+    entity_builder::PhysicsEntityBuilder,
+    terrain, trigger,
+};
+
+/// Scene description, loaded from the RON files in `assets/scenes/`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SceneDesc {
+    pub lights: Vec<LightDesc>,
+    pub bodies: Vec<BodyDesc>,
+    pub terrain: Option<TerrainDesc>,
+    pub triggers: Vec<TriggerDesc>,
+    /// Where the character is created; it's also a respawn point.
+    pub player_spawn: [f32; 3],
+    /// Other points where the character can be respawned.
+    pub respawn_points: Vec<[f32; 3]>,
+}
+
+/// Terrain built from a grayscale heightmap.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TerrainDesc {
+    /// Image path, relative to the assets directory.
+    pub heightmap: String,
+    /// Extent on X and Z, and the height of the white pixels on Y.
+    pub size: [f32; 3],
+    pub position: [f32; 3],
+    pub color: [f32; 4],
+}
+
+/// Invisible zone that reports the bodies entering and exiting it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TriggerDesc {
+    pub name: String,
+    pub shape: SceneShape,
+    pub position: [f32; 3],
+    /// When `true` the character entering it is respawned.
+    #[serde(default)]
+    pub kill_zone: bool,
+}
+
+/// Directional light.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LightDesc {
+    pub color: [f32; 3],
+    pub direction: [f32; 3],
+    pub intensity: f32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum SceneShape {
+    Cube {
+        half_extents: [f32; 3],
+    },
+    Sphere {
+        radius: f32,
+    },
+    Capsule {
+        half_height: f32,
+        radius: f32,
+    },
+    /// Many shapes, each with its own offset, attached to the same body.
+    Compound {
+        parts: Vec<CompoundPart>,
+    },
+}
+
+impl SceneShape {
+    pub fn shape_desc(&self) -> ShapeDesc<f32> {
+        match self {
+            SceneShape::Cube { half_extents } => ShapeDesc::Cube {
+                half_extents: Vector3::from(*half_extents),
+            },
+            SceneShape::Sphere { radius } => ShapeDesc::Sphere { radius: *radius },
+            SceneShape::Capsule {
+                half_height,
+                radius,
+            } => ShapeDesc::Capsule {
+                half_height: *half_height,
+                radius: *radius,
+            },
+            SceneShape::Compound { parts } => ShapeDesc::Compound {
+                shapes: parts
+                    .iter()
+                    .map(|part| (part.isometry(), part.shape.shape_desc()))
+                    .collect(),
+            },
+        }
+    }
+}
+
+/// Shape of a compound, placed relative to the body.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CompoundPart {
+    pub shape: SceneShape,
+    pub offset: [f32; 3],
+    /// Euler angles, in degrees.
+    #[serde(default)]
+    pub rotation: [f32; 3],
+}
+
+impl CompoundPart {
+    pub fn new(shape: SceneShape, offset: [f32; 3]) -> Self {
+        CompoundPart {
+            shape,
+            offset,
+            rotation: [0.0; 3],
+        }
+    }
+
+    pub fn isometry(&self) -> Isometry3<f32> {
+        Isometry3::from_parts(
+            Translation3::from(Vector3::from(self.offset)),
+            UnitQuaternion::from_euler_angles(
+                self.rotation[0].to_radians(),
+                self.rotation[1].to_radians(),
+                self.rotation[2].to_radians(),
+            ),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub enum SceneBodyMode {
+    Static,
+    Dynamic,
+    Kinematic,
+}
+
+impl From<SceneBodyMode> for BodyMode {
+    fn from(mode: SceneBodyMode) -> Self {
+        match mode {
+            SceneBodyMode::Static => BodyMode::Static,
+            SceneBodyMode::Dynamic => BodyMode::Dynamic,
+            SceneBodyMode::Kinematic => BodyMode::Kinematic,
+        }
+    }
+}
+
+/// Rigid body with its collider and visual.
+///
+/// The optional parameters, when omitted, take the `RigidBodyDesc` default.
+/// It's also used to create the bodies from code, through `create_body_entity`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BodyDesc {
+    pub shape: SceneShape,
+    pub mode: SceneBodyMode,
+    pub position: [f32; 3],
+    #[serde(default)]
+    pub mass: Option<f32>,
+    #[serde(default)]
+    pub friction: Option<f32>,
+    #[serde(default)]
+    pub bounciness: Option<f32>,
+    /// When `false` the character can't push the body.
+    #[serde(default = "default_pushable")]
+    pub pushable: bool,
+    /// Bodies without visual are invisible.
+    #[serde(default)]
+    pub visual: Option<VisualDesc>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VisualDesc {
+    pub color: [f32; 4],
+    #[serde(default)]
+    pub metallic: f32,
+    #[serde(default = "default_roughness")]
+    pub roughness: f32,
+}
+
+fn default_roughness() -> f32 {
+    1.0
+}
+
+fn default_pushable() -> bool {
+    true
+}
+
+/// Loads the scene file and creates its lights and bodies.
+///
+/// Returns the scene, so the caller can use the player spawn.
+pub fn load_scene(
+    world: &mut World,
+    path: impl AsRef<std::path::Path>,
+) -> Result<SceneDesc, Error> {
+    let scene = SceneDesc::load(path)?;
+    build_scene(world, &scene)?;
+    Ok(scene)
+}
+
+pub fn build_scene(world: &mut World, scene: &SceneDesc) -> Result<(), Error> {
+    for light in &scene.lights {
+        add_light_entity(
+            world,
+            Srgb::new(light.color[0], light.color[1], light.color[2]),
+            Vector3::from(light.direction),
+            light.intensity,
+        );
+    }
+
+    for body in &scene.bodies {
+        create_body_entity(world, body);
+    }
+
+    for desc in &scene.triggers {
+        let trigger = trigger::create_trigger_entity(
+            world,
+            desc.name.clone(),
+            &desc.shape.shape_desc(),
+            Vector3::from(desc.position),
+        );
+        if desc.kill_zone {
+            world
+                .write_storage()
+                .insert(trigger, KillZone)
+                .expect("The entity is alive");
+        }
+    }
+
+    for position in std::iter::once(&scene.player_spawn).chain(&scene.respawn_points) {
+        let mut transform = Transform::default();
+        transform.set_translation(Vector3::from(*position));
+        world
+            .create_entity()
+            .with(transform)
+            .with(RespawnPoint)
+            .build();
+    }
+
+    if let Some(terrain) = &scene.terrain {
+        terrain::create_terrain_entity(
+            world,
+            application_root_dir()?
+                .join("assets")
+                .join(&terrain.heightmap),
+            Vector3::from(terrain.size),
+            Vector3::from(terrain.position),
+            LinSrgba::new(
+                terrain.color[0],
+                terrain.color[1],
+                terrain.color[2],
+                terrain.color[3],
+            ),
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn add_light_entity(world: &mut World, color: Srgb, direction: Vector3<f32>, intensity: f32) {
+    let light: light::Light = light::DirectionalLight {
+        color,
+        direction: direction.normalize(),
+        intensity,
+    }
+    .into();
+
+    world.create_entity().with(light).build();
+}
+
+pub fn create_body_entity(world: &mut World, desc: &BodyDesc) -> Entity {
+    let mut builder = PhysicsEntityBuilder::new()
+        .shape(desc.shape.shape_desc())
+        .mode(desc.mode.into())
+        .at(Vector3::from(desc.position));
+
+    if let Some(mass) = desc.mass {
+        builder = builder.mass(mass);
+    }
+    if let Some(friction) = desc.friction {
+        builder = builder.friction(friction);
+    }
+    if let Some(bounciness) = desc.bounciness {
+        builder = builder.bounciness(bounciness);
+    }
+
+    builder = match &desc.visual {
+        Some(visual) => builder
+            .color(LinSrgba::new(
+                visual.color[0],
+                visual.color[1],
+                visual.color[2],
+                visual.color[3],
+            ))
+            .metallic_roughness(visual.metallic, visual.roughness),
+        None => builder.invisible(),
+    };
+
+    let entity = builder.build(world);
+    if !desc.pushable {
+        world
+            .write_storage()
+            .insert(entity, NotPushable)
+            .expect("The entity is alive");
+    }
+    entity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_loads() {
+        let path =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/scenes/test_level.ron");
+        let scene = SceneDesc::load(path).expect("The scene is valid");
+
+        assert!(!scene.lights.is_empty());
+        assert!(!scene.bodies.is_empty());
+        assert!(scene.terrain.is_some());
+    }
+}
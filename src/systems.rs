@@ -1,17 +1,23 @@
+use std::collections::HashSet;
+
 use amethyst::{
     controls::HideCursor,
     core::{
-        math::{Matrix4, UnitQuaternion, Vector3},
-        Transform,
+        math::{Isometry3, Matrix4, Translation3, UnitQuaternion, Vector3},
+        timing::Time,
+        Hidden, Transform,
     },
     ecs::prelude::*,
     input::{InputEvent, StringBindings},
-    renderer::Camera,
+    renderer::{palette::LinSrgba, Camera},
     shrev::EventChannel,
 };
 use amethyst_physics::prelude::*;
+use rand::Rng;
 
-use crate::components::*;
+use crate::{
+    components::*, entity_builder::PhysicsEntityBuilder, resources::*, trigger::TriggerEntered,
+};
 
 const MOUSE_SENSITIVITY: f32 = 0.2;
 const MAX_PITCH_ANGLE: f32 = 20.0;
@@ -20,6 +26,17 @@ const JUMP_IMPULSE: f32 = 55.0;
 const JUMP_TIME: f32 = 0.25;
 const JUMP_DRAG_POWER: f32 = 5.0;
 const JUMP_MAX_BF: f32 = 0.7;
+/// Colors of the spawned bodies; kept small since each color is a cached material.
+const SPAWN_PALETTE: [(f32, f32, f32); 8] = [
+    (0.9, 0.2, 0.2),
+    (0.2, 0.7, 0.2),
+    (0.2, 0.3, 0.9),
+    (0.9, 0.8, 0.2),
+    (0.8, 0.3, 0.8),
+    (0.2, 0.8, 0.8),
+    (0.9, 0.5, 0.1),
+    (0.9, 0.9, 0.9),
+];
 
 #[derive(Debug)]
 pub(crate) struct CameraMotionSystem {
@@ -139,27 +156,104 @@ impl CharacterMotionControllerSystem {
     }
 }
 
+impl CharacterMotionControllerSystem {
+    /// Pushes the dynamic bodies touched on the side by the character.
+    ///
+    /// The pushed body is brought to a speed that depends on how fast the
+    /// character walks into it and on their masses, so a light box flies away
+    /// while a heavy crate barely moves.
+    /// The masses are read from the `Mass` components; the bodies without one
+    /// are never pushed.
+    fn push_bodies(
+        &self,
+        physics_world: &PhysicsWorld<f32>,
+        config: &CharacterPushConfig,
+        character: Entity,
+        rigid_body_tags: &ReadStorage<'_, PhysicsHandle<PhysicsRigidBodyTag>>,
+        masses: &ReadStorage<'_, Mass>,
+        not_pushables: &ReadStorage<'_, NotPushable>,
+    ) {
+        let rb_server = physics_world.rigid_body_server();
+        let (character_body, character_mass) =
+            match (rigid_body_tags.get(character), masses.get(character)) {
+                (Some(body), Some(mass)) => (body.get(), mass.0),
+                _ => return,
+            };
+        let character_velocity = rb_server.linear_velocity(character_body);
+
+        for contact in &self.contact_events {
+            let other = match contact.other_entity {
+                Some(other) if !not_pushables.contains(other) => other,
+                _ => continue,
+            };
+            let other_body = match rigid_body_tags.get(other) {
+                Some(other_body) => other_body.get(),
+                None => continue,
+            };
+            if rb_server.mode(other_body) != BodyMode::Dynamic {
+                continue;
+            }
+            let other_mass = match masses.get(other) {
+                Some(mass) if mass.0 <= config.max_mass => mass.0,
+                _ => continue,
+            };
+
+            // Only horizontal pushes, the ground contacts are skipped.
+            let mut direction = -contact.normal.into_inner();
+            direction.y = 0.0;
+            if direction.norm() < 0.5 {
+                continue;
+            }
+            direction.normalize_mut();
+
+            let approach_speed = character_velocity.dot(&direction);
+            if approach_speed < config.min_approach_speed {
+                continue;
+            }
+
+            let target_speed =
+                approach_speed * config.strength * character_mass / (character_mass + other_mass);
+            let other_speed = rb_server.linear_velocity(other_body).dot(&direction);
+            if other_speed < target_speed {
+                rb_server.apply_impulse(
+                    other_body,
+                    &(direction * (target_speed - other_speed) * other_mass),
+                );
+            }
+        }
+    }
+}
+
 impl<'s> System<'s> for CharacterMotionControllerSystem {
+    #[allow(clippy::type_complexity)]
     type SystemData = (
+        Entities<'s>,
         ReadExpect<'s, PhysicsWorld<f32>>,
         ReadExpect<'s, PhysicsTime>,
         ReadExpect<'s, EventChannel<InputEvent<StringBindings>>>,
+        Read<'s, CharacterPushConfig>,
         ReadStorage<'s, CharacterBody>,
         ReadStorage<'s, Camera>,
         ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
         ReadStorage<'s, Transform>,
+        ReadStorage<'s, Mass>,
+        ReadStorage<'s, NotPushable>,
     );
 
     fn run(
         &mut self,
         (
+            entities,
             physics_world,
             physics_time,
             input_event_channel,
+            push_config,
             character_bodies,
             cameras,
             rigid_body_tags,
             transforms,
+            masses,
+            not_pushables,
         ): Self::SystemData,
     ) {
         for e in input_event_channel.read(self.input_event_reader.as_mut().unwrap()) {
@@ -209,7 +303,7 @@ impl<'s> System<'s> for CharacterMotionControllerSystem {
             camera_pos = t.global_matrix().clone();
         }
 
-        for (body_tag, _) in (&rigid_body_tags, &character_bodies).join() {
+        for (character, body_tag, _) in (&entities, &rigid_body_tags, &character_bodies).join() {
             let is_in_air = {
                 let mut is_in_air = true;
 
@@ -238,6 +332,15 @@ impl<'s> System<'s> for CharacterMotionControllerSystem {
                 is_in_air
             };
 
+            self.push_bodies(
+                &physics_world,
+                &push_config,
+                character,
+                &rigid_body_tags,
+                &masses,
+                &not_pushables,
+            );
+
             let mut motion_factor = 1.0;
             let mut breaking_factor = 1.0;
             if !is_in_air {
@@ -284,3 +387,460 @@ impl<'s> System<'s> for CharacterMotionControllerSystem {
         self.input_event_reader = Some(ie.register_reader());
     }
 }
+
+/// Restores the last physics pose on the interpolated entities, so the physics
+/// sync doesn't take the blended `Transform` as a teleport.
+///
+/// Must run after the transform system, so the blended global matrix is kept
+/// for the rendering, and before the physics transform sync, behind a barrier.
+#[derive(Debug, Default)]
+pub struct TransformInterpolationRestoreSystem;
+
+impl<'s> System<'s> for TransformInterpolationRestoreSystem {
+    type SystemData = (
+        ReadStorage<'s, TransformInterpolation>,
+        WriteStorage<'s, Transform>,
+    );
+
+    fn run(&mut self, (interpolations, mut transforms): Self::SystemData) {
+        for (transform, interpolation) in (&mut transforms, &interpolations).join() {
+            if interpolation.initialized {
+                *transform.isometry_mut() = interpolation.current;
+            }
+        }
+    }
+}
+
+/// Stores the pose of the interpolated rigid bodies after each physics step.
+///
+/// Runs in the post physics stage.
+#[derive(Debug, Default)]
+pub struct TransformInterpolationCaptureSystem;
+
+impl<'s> System<'s> for TransformInterpolationCaptureSystem {
+    type SystemData = (
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
+        WriteStorage<'s, TransformInterpolation>,
+    );
+
+    fn run(&mut self, (physics_world, rigid_body_tags, mut interpolations): Self::SystemData) {
+        for (body_tag, interpolation) in (&rigid_body_tags, &mut interpolations).join() {
+            let pose = physics_world.rigid_body_server().transform(body_tag.get());
+
+            if interpolation.initialized {
+                interpolation.previous = interpolation.current;
+            } else {
+                interpolation.previous = pose;
+                interpolation.initialized = true;
+            }
+            interpolation.current = pose;
+        }
+    }
+}
+
+/// Blends the `Transform` of the interpolated entities between the last two
+/// physics poses, using the time not yet consumed by the fixed physics step.
+///
+/// Must run before the transform system, so the blended pose is the one
+/// rendered. Since the physics batch runs later in the frame, it blends the
+/// poses captured during the previous frame, with the time that frame left over.
+#[derive(Debug, Default)]
+pub struct TransformInterpolationSystem {
+    /// Mirrors the time bank of the physics stepper, as of the previous frame.
+    time_bank: f32,
+}
+
+impl<'s> System<'s> for TransformInterpolationSystem {
+    type SystemData = (
+        Read<'s, Time>,
+        ReadExpect<'s, PhysicsTime>,
+        ReadStorage<'s, TransformInterpolation>,
+        WriteStorage<'s, Transform>,
+    );
+
+    fn run(&mut self, (time, physics_time, interpolations, mut transforms): Self::SystemData) {
+        let step = physics_time.delta_seconds();
+        let alpha = self.time_bank / step;
+        self.time_bank = (self.time_bank + time.delta_seconds()) % step;
+
+        for (transform, interpolation) in (&mut transforms, &interpolations).join() {
+            if !interpolation.initialized {
+                continue;
+            }
+
+            let translation = interpolation
+                .previous
+                .translation
+                .vector
+                .lerp(&interpolation.current.translation.vector, alpha);
+            let rotation = interpolation
+                .previous
+                .rotation
+                .slerp(&interpolation.current.rotation, alpha);
+
+            *transform.isometry_mut() =
+                Isometry3::from_parts(Translation3::from(translation), rotation);
+        }
+    }
+}
+
+/// Spawns the bodies of each `Spawner`.
+///
+/// The entity is reserved here, while its shape, body and visual are created
+/// at the end of the frame by the `PhysicsEntityBuilder`.
+/// The pooling spawners reuse their released bodies when possible.
+#[derive(Debug, Default)]
+pub struct CubeSpawnerSystem;
+
+impl<'s> System<'s> for CubeSpawnerSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, Time>,
+        Read<'s, LazyUpdate>,
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, Spawner>,
+        WriteStorage<'s, Pooled>,
+        WriteStorage<'s, Hidden>,
+        WriteStorage<'s, Lifetime>,
+        WriteStorage<'s, TransformInterpolation>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            time,
+            lazy,
+            physics_world,
+            rigid_body_tags,
+            mut transforms,
+            mut spawners,
+            mut pooled,
+            mut hiddens,
+            mut lifetimes,
+            mut interpolations,
+        ): Self::SystemData,
+    ) {
+        // Pooled entities to enable again: (entity, position, velocity, lifetime).
+        let mut reused = Vec::new();
+
+        for (spawner_entity, spawner, transform) in (&entities, &mut spawners, &transforms).join() {
+            spawner.alive.retain(|e| entities.is_alive(*e));
+            spawner.free.retain(|e| entities.is_alive(*e));
+            spawner.time_bank += time.delta_seconds();
+
+            // The interval is public, so it's clamped again to never loop forever.
+            let interval = spawner.interval.max(MIN_SPAWN_INTERVAL);
+            while spawner.time_bank > interval {
+                spawner.time_bank -= interval;
+
+                if spawner.alive.len() >= spawner.max_alive {
+                    continue;
+                }
+
+                let offset = Vector3::new(
+                    spawner.rng.gen_range(-1.0, 1.0) * spawner.half_extents.x,
+                    spawner.rng.gen_range(-1.0, 1.0) * spawner.half_extents.y,
+                    spawner.rng.gen_range(-1.0, 1.0) * spawner.half_extents.z,
+                );
+                let position = transform.translation() + offset;
+                let velocity = spawner.initial_velocity;
+                let lifetime = spawner.lifetime;
+
+                if let Some(entity) = spawner.free.pop() {
+                    spawner.alive.push(entity);
+                    reused.push((entity, position, velocity, lifetime));
+                    continue;
+                }
+
+                let (r, g, b) = SPAWN_PALETTE[spawner.rng.gen_range(0, SPAWN_PALETTE.len())];
+                let color = LinSrgba::new(r, g, b, 1.0);
+                let builder = match spawner.shape {
+                    SpawnShape::Cube { half_extents } => {
+                        PhysicsEntityBuilder::new().cube(half_extents)
+                    }
+                    SpawnShape::Sphere { radius } => PhysicsEntityBuilder::new().sphere(radius),
+                };
+
+                let entity = builder
+                    .dynamic()
+                    .at(position)
+                    .velocity(velocity)
+                    .color(color)
+                    .build_lazy(&entities, &lazy);
+                spawner.alive.push(entity);
+
+                if let Some(seconds) = lifetime {
+                    lazy.insert(entity, Lifetime::new(seconds));
+                }
+                if spawner.pooling {
+                    lazy.insert(entity, Pooled::new(spawner_entity));
+                }
+            }
+        }
+
+        for (entity, position, velocity, lifetime) in reused {
+            if let Some(body_tag) = rigid_body_tags.get(entity) {
+                let rb_server = physics_world.rigid_body_server();
+                rb_server.set_transform(
+                    body_tag.get(),
+                    &Isometry3::translation(position.x, position.y, position.z),
+                );
+                rb_server.set_linear_velocity(body_tag.get(), &velocity);
+                rb_server.set_angular_velocity(body_tag.get(), &Vector3::zeros());
+                rb_server.set_mode(body_tag.get(), BodyMode::Dynamic);
+            }
+            if let Some(transform) = transforms.get_mut(entity) {
+                *transform.isometry_mut() =
+                    Isometry3::translation(position.x, position.y, position.z);
+            }
+            if let Some(interpolation) = interpolations.get_mut(entity) {
+                interpolation.initialized = false;
+            }
+            if let Some(pooled) = pooled.get_mut(entity) {
+                pooled.active = true;
+            }
+            if let Some(seconds) = lifetime {
+                lifetimes
+                    .insert(entity, Lifetime::new(seconds))
+                    .expect("The entity is alive");
+            }
+            hiddens.remove(entity);
+        }
+    }
+}
+
+/// Deletes the entities with an expired `Lifetime`, and the bodies that leave
+/// the `KillVolume`.
+///
+/// The physics handles are dropped with the entity components, so the bodies
+/// and shapes are released by the physics servers too.
+/// The `Pooled` entities are not deleted, instead their body is disabled and
+/// they are given back to their spawner.
+/// The character is never deleted.
+#[derive(Debug, Default)]
+pub struct DespawnSystem;
+
+impl<'s> System<'s> for DespawnSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Entities<'s>,
+        Read<'s, Time>,
+        Read<'s, KillVolume>,
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        ReadStorage<'s, Transform>,
+        ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
+        ReadStorage<'s, CharacterBody>,
+        WriteStorage<'s, Lifetime>,
+        WriteStorage<'s, Pooled>,
+        WriteStorage<'s, Spawner>,
+        WriteStorage<'s, Hidden>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            time,
+            kill_volume,
+            physics_world,
+            transforms,
+            rigid_body_tags,
+            character_bodies,
+            mut lifetimes,
+            mut pooled,
+            mut spawners,
+            mut hiddens,
+        ): Self::SystemData,
+    ) {
+        let mut expired = Vec::new();
+
+        for (entity, lifetime) in (&entities, &mut lifetimes).join() {
+            lifetime.remaining -= time.delta_seconds();
+            if lifetime.remaining <= 0.0 {
+                expired.push(entity);
+            }
+        }
+
+        for (entity, transform, _, _) in
+            (&entities, &transforms, &rigid_body_tags, !&character_bodies).join()
+        {
+            if let Some(pooled) = pooled.get(entity) {
+                if !pooled.active {
+                    // Already released.
+                    continue;
+                }
+            }
+
+            if !kill_volume.contains(transform.translation()) {
+                expired.push(entity);
+            }
+        }
+
+        for entity in expired {
+            let pooled = match pooled.get_mut(entity) {
+                Some(pooled) if pooled.active => pooled,
+                Some(_) => continue,
+                None => {
+                    // May be already deleted by its lifetime.
+                    let _ = entities.delete(entity);
+                    continue;
+                }
+            };
+
+            let spawner = match spawners.get_mut(pooled.spawner) {
+                Some(spawner) => spawner,
+                None => {
+                    // The spawner is gone, nothing to give this entity back.
+                    entities.delete(entity).expect("The entity is alive");
+                    continue;
+                }
+            };
+
+            pooled.active = false;
+            spawner.alive.retain(|e| *e != entity);
+            spawner.free.push(entity);
+
+            if let Some(body_tag) = rigid_body_tags.get(entity) {
+                physics_world
+                    .rigid_body_server()
+                    .set_mode(body_tag.get(), BodyMode::Disabled);
+            }
+            lifetimes.remove(entity);
+            hiddens.insert(entity, Hidden).expect("The entity is alive");
+        }
+    }
+}
+
+/// Teleports the character back to the closest `RespawnPoint` when it leaves
+/// the `KillVolume`, or enters a `KillZone` trigger.
+///
+/// The body velocity is zeroed and the camera boom rotation is reset.
+#[derive(Default)]
+pub struct CharacterRespawnSystem {
+    trigger_entered_reader: Option<ReaderId<TriggerEntered>>,
+}
+
+impl<'s> System<'s> for CharacterRespawnSystem {
+    #[allow(clippy::type_complexity)]
+    type SystemData = (
+        Read<'s, KillVolume>,
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        Read<'s, EventChannel<TriggerEntered>>,
+        ReadStorage<'s, CharacterBody>,
+        ReadStorage<'s, CameraBoomHandle>,
+        ReadStorage<'s, RespawnPoint>,
+        ReadStorage<'s, KillZone>,
+        ReadStorage<'s, PhysicsHandle<PhysicsRigidBodyTag>>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, TransformInterpolation>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            kill_volume,
+            physics_world,
+            trigger_entered_events,
+            character_bodies,
+            camera_boom_handles,
+            respawn_points,
+            kill_zones,
+            rigid_body_tags,
+            mut transforms,
+            mut interpolations,
+        ): Self::SystemData,
+    ) {
+        let mut in_kill_zone = false;
+        for e in trigger_entered_events.read(self.trigger_entered_reader.as_mut().unwrap()) {
+            if kill_zones.contains(e.trigger) && character_bodies.contains(e.other) {
+                in_kill_zone = true;
+            }
+        }
+
+        let mut respawned = false;
+        for (transform, body_tag, interpolation, _) in (
+            &transforms,
+            &rigid_body_tags,
+            (&mut interpolations).maybe(),
+            &character_bodies,
+        )
+            .join()
+        {
+            let position = *transform.translation();
+            if !in_kill_zone && kill_volume.contains(&position) {
+                break;
+            }
+
+            let respawn_position = (&transforms, &respawn_points)
+                .join()
+                .map(|(t, _)| *t.translation())
+                .min_by(|a, b| {
+                    (a - position)
+                        .norm_squared()
+                        .total_cmp(&(b - position).norm_squared())
+                });
+            let respawn_position = match respawn_position {
+                Some(respawn_position) => respawn_position,
+                None => break,
+            };
+
+            let rb_server = physics_world.rigid_body_server();
+            rb_server.set_transform(
+                body_tag.get(),
+                &Isometry3::translation(respawn_position.x, respawn_position.y, respawn_position.z),
+            );
+            rb_server.set_linear_velocity(body_tag.get(), &Vector3::zeros());
+            rb_server.set_angular_velocity(body_tag.get(), &Vector3::zeros());
+
+            if let Some(interpolation) = interpolation {
+                interpolation.initialized = false;
+            }
+            respawned = true;
+
+            break; // Actually only 1 player is allowed;
+        }
+
+        if !respawned {
+            return;
+        }
+
+        for (transform, _) in (&mut transforms, &camera_boom_handles).join() {
+            transform.set_rotation(UnitQuaternion::<f32>::identity());
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.trigger_entered_reader = Some(
+            world
+                .fetch_mut::<EventChannel<TriggerEntered>>()
+                .register_reader(),
+        );
+    }
+}
+
+/// Drops from the `ShapeRegistry` the descriptions of the shapes no entity
+/// uses anymore, so the deleted meshes are freed and the tags of the removed
+/// shapes can be reused.
+#[derive(Debug, Default)]
+pub struct ShapeRegistryCleanupSystem {
+    used: HashSet<PhysicsShapeTag>,
+}
+
+impl<'s> System<'s> for ShapeRegistryCleanupSystem {
+    type SystemData = (
+        Write<'s, ShapeRegistry>,
+        ReadStorage<'s, PhysicsHandle<PhysicsShapeTag>>,
+    );
+
+    fn run(&mut self, (mut shape_registry, shapes): Self::SystemData) {
+        self.used.clear();
+        self.used.extend(shapes.join().map(|s| s.get()));
+        shape_registry.retain_used(&self.used);
+    }
+}
@@ -0,0 +1,103 @@
+use std::path::Path;
+
+use amethyst::{
+    core::math::{Point3, Vector3},
+    ecs::Entity,
+    prelude::*,
+    renderer::palette::LinSrgba,
+    Error,
+};
+use amethyst_physics::prelude::*;
+
+use crate::{entity_builder::PhysicsEntityBuilder, mesh_collider::MeshGeometry, visual_utils};
+
+/// Grid of heights, loaded from a grayscale image.
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    /// Samples on the X axis.
+    pub width: usize,
+    /// Samples on the Z axis.
+    pub depth: usize,
+    /// Row major heights, from `0.0` (black) to `1.0` (white).
+    pub heights: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let image = image::open(path)?.to_luma();
+        let (width, depth) = image.dimensions();
+        if width < 2 || depth < 2 {
+            return Err(Error::from_string("The heightmap must be at least 2x2"));
+        }
+
+        Ok(Heightmap {
+            width: width as usize,
+            depth: depth as usize,
+            heights: image.pixels().map(|p| f32::from(p[0]) / 255.0).collect(),
+        })
+    }
+
+    pub fn height(&self, x: usize, z: usize) -> f32 {
+        self.heights[z * self.width + x]
+    }
+
+    /// Triangulates the grid, centered on the origin on the X and Z axes.
+    ///
+    /// `size` is the terrain extent on X and Z, and the height of the white
+    /// samples on Y.
+    pub fn geometry(&self, size: &Vector3<f32>) -> MeshGeometry {
+        let step_x = size.x / (self.width - 1) as f32;
+        let step_z = size.z / (self.depth - 1) as f32;
+
+        let mut points = Vec::with_capacity(self.width * self.depth);
+        for z in 0..self.depth {
+            for x in 0..self.width {
+                points.push(Point3::new(
+                    x as f32 * step_x - size.x * 0.5,
+                    self.height(x, z) * size.y,
+                    z as f32 * step_z - size.z * 0.5,
+                ));
+            }
+        }
+
+        let mut indices = Vec::with_capacity((self.width - 1) * (self.depth - 1) * 2);
+        for z in 0..self.depth - 1 {
+            for x in 0..self.width - 1 {
+                let i = z * self.width + x;
+                // Counter clockwise, seen from above.
+                indices.push(Point3::new(i, i + self.width, i + 1));
+                indices.push(Point3::new(i + 1, i + self.width, i + self.width + 1));
+            }
+        }
+
+        MeshGeometry { points, indices }
+    }
+}
+
+/// Creates the static terrain entity; the render mesh and the triangle mesh
+/// collider are built from the same heightmap.
+pub fn create_terrain_entity(
+    world: &mut World,
+    heightmap_path: impl AsRef<Path>,
+    size: Vector3<f32>,
+    position: Vector3<f32>,
+    color: LinSrgba,
+) -> Result<Entity, Error> {
+    let geometry = Heightmap::load(heightmap_path)?.geometry(&size);
+
+    let mesh = visual_utils::create_mesh(
+        world,
+        visual_utils::mesh_data_from_triangles(&geometry.points, &geometry.indices),
+    );
+
+    Ok(PhysicsEntityBuilder::new()
+        .shape(ShapeDesc::TriMesh {
+            points: geometry.points,
+            indices: geometry.indices,
+        })
+        .static_body()
+        .at(position)
+        .mesh(mesh)
+        .color(color)
+        .build(world))
+}
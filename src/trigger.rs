@@ -0,0 +1,144 @@
+use amethyst::{
+    core::{math::Vector3, Transform},
+    ecs::prelude::*,
+    shrev::EventChannel,
+};
+use amethyst_physics::{prelude::*, servers::AreaDesc};
+
+use crate::{
+    components::{CharacterBody, TriggerVolume},
+    resources::ShapeRegistry,
+};
+
+/// Published on the `EventChannel<TriggerEntered>` when a body enters a
+/// `TriggerVolume`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriggerEntered {
+    pub trigger: Entity,
+    pub other: Entity,
+}
+
+/// Published on the `EventChannel<TriggerExited>` when a body exits a
+/// `TriggerVolume`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriggerExited {
+    pub trigger: Entity,
+    pub other: Entity,
+}
+
+/// Creates an entity with a physics area, that reports the overlapping bodies
+/// through the `TriggerEventsSystem`.
+pub fn create_trigger_entity(
+    world: &mut World,
+    name: impl Into<String>,
+    shape_desc: &ShapeDesc<f32>,
+    position: Vector3<f32>,
+) -> Entity {
+    let (shape, area) = {
+        let physics_world = world.fetch::<PhysicsWorld<f32>>();
+        let shape = world
+            .fetch_mut::<ShapeRegistry>()
+            .get_or_create(&physics_world, shape_desc);
+        let area = physics_world.area_server().create(&AreaDesc::default());
+        (shape, area)
+    };
+
+    let mut transform = Transform::default();
+    transform.set_translation(position);
+
+    world
+        .create_entity()
+        .with(transform)
+        .with(shape)
+        .with(area)
+        .with(TriggerVolume::new(name))
+        .build()
+}
+
+/// Publishes the overlap events of the `TriggerVolume` areas as
+/// `TriggerEntered` and `TriggerExited` events.
+///
+/// It runs after each physics step, since the areas report only the events
+/// of the last step.
+/// The bodies without an entity are ignored.
+#[derive(Debug, Default)]
+pub struct TriggerEventsSystem;
+
+impl<'s> System<'s> for TriggerEventsSystem {
+    type SystemData = (
+        Entities<'s>,
+        ReadExpect<'s, PhysicsWorld<f32>>,
+        Write<'s, EventChannel<TriggerEntered>>,
+        Write<'s, EventChannel<TriggerExited>>,
+        ReadStorage<'s, TriggerVolume>,
+        ReadStorage<'s, PhysicsHandle<PhysicsAreaTag>>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, physics_world, mut entered_events, mut exited_events, triggers, areas): Self::SystemData,
+    ) {
+        let area_server = physics_world.area_server();
+        for (trigger, _, area) in (&entities, &triggers, &areas).join() {
+            for e in area_server.overlap_events(area.get()) {
+                match e {
+                    OverlapEvent::Enter(_, Some(other)) => {
+                        entered_events.single_write(TriggerEntered { trigger, other })
+                    }
+                    OverlapEvent::Exit(_, Some(other)) => {
+                        exited_events.single_write(TriggerExited { trigger, other })
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// Logs when the character enters and exits a trigger.
+#[derive(Default)]
+pub struct CharacterZoneLogSystem {
+    entered_reader: Option<ReaderId<TriggerEntered>>,
+    exited_reader: Option<ReaderId<TriggerExited>>,
+}
+
+impl<'s> System<'s> for CharacterZoneLogSystem {
+    type SystemData = (
+        Read<'s, EventChannel<TriggerEntered>>,
+        Read<'s, EventChannel<TriggerExited>>,
+        ReadStorage<'s, TriggerVolume>,
+        ReadStorage<'s, CharacterBody>,
+    );
+
+    fn run(&mut self, (entered_events, exited_events, triggers, characters): Self::SystemData) {
+        let entered = entered_events
+            .read(self.entered_reader.as_mut().unwrap())
+            .map(|e| (e.trigger, e.other, "entered"));
+        let exited = exited_events
+            .read(self.exited_reader.as_mut().unwrap())
+            .map(|e| (e.trigger, e.other, "exited"));
+
+        for (trigger, other, verb) in entered.chain(exited) {
+            if !characters.contains(other) {
+                continue;
+            }
+            if let Some(trigger) = triggers.get(trigger) {
+                log::info!("The character {} the zone `{}`", verb, trigger.name);
+            }
+        }
+    }
+
+    fn setup(&mut self, world: &mut World) {
+        Self::SystemData::setup(world);
+        self.entered_reader = Some(
+            world
+                .fetch_mut::<EventChannel<TriggerEntered>>()
+                .register_reader(),
+        );
+        self.exited_reader = Some(
+            world
+                .fetch_mut::<EventChannel<TriggerExited>>()
+                .register_reader(),
+        );
+    }
+}
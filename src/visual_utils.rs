@@ -1,8 +1,350 @@
+use std::collections::HashMap;
+
 use amethyst::{
     assets::{AssetStorage, Handle, Loader},
+    core::math::{Point3, Vector3},
     ecs::prelude::*,
-    renderer::{mtl, palette::LinSrgba, rendy::texture, types},
+    renderer::{
+        mtl,
+        palette::LinSrgba,
+        rendy::{
+            mesh::{MeshBuilder, Normal, Position, Tangent, TexCoord},
+            texture,
+        },
+        shape::Shape,
+        types,
+    },
 };
+use amethyst_physics::prelude::*;
+use ncollide3d::{procedural::IndexBuffer, transformation};
+
+/// Segments used around the Y axis by the revolved meshes.
+const REVOLVE_SEGMENTS: usize = 24;
+/// Rings used by each hemisphere of the capsule.
+const CAPSULE_RINGS: usize = 8;
+
+/// Identifies a generated mesh; the sizes are stored as bits so they can be hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MeshKey {
+    Cube { half_extents: [u32; 3] },
+    Sphere { radius: u32 },
+    Capsule { half_height: u32, radius: u32 },
+    Cylinder { half_height: u32, radius: u32 },
+}
+
+impl MeshKey {
+    pub fn cube(half_extents: &Vector3<f32>) -> Self {
+        MeshKey::Cube {
+            half_extents: [
+                half_extents.x.to_bits(),
+                half_extents.y.to_bits(),
+                half_extents.z.to_bits(),
+            ],
+        }
+    }
+
+    pub fn sphere(radius: f32) -> Self {
+        MeshKey::Sphere {
+            radius: radius.to_bits(),
+        }
+    }
+
+    pub fn capsule(half_height: f32, radius: f32) -> Self {
+        MeshKey::Capsule {
+            half_height: half_height.to_bits(),
+            radius: radius.to_bits(),
+        }
+    }
+
+    pub fn cylinder(half_height: f32, radius: f32) -> Self {
+        MeshKey::Cylinder {
+            half_height: half_height.to_bits(),
+            radius: radius.to_bits(),
+        }
+    }
+
+    /// Returns the key of the analytic shapes, `None` for the others.
+    pub fn from_shape_desc(desc: &ShapeDesc<f32>) -> Option<Self> {
+        match desc {
+            ShapeDesc::Cube { half_extents } => Some(MeshKey::cube(half_extents)),
+            ShapeDesc::Sphere { radius } => Some(MeshKey::sphere(*radius)),
+            ShapeDesc::Capsule {
+                half_height,
+                radius,
+            } => Some(MeshKey::capsule(*half_height, *radius)),
+            ShapeDesc::Cylinder {
+                half_height,
+                radius,
+            } => Some(MeshKey::cylinder(*half_height, *radius)),
+            _ => None,
+        }
+    }
+
+    fn generate(&self) -> types::MeshData {
+        let (shape, scale) = match *self {
+            MeshKey::Cube { half_extents } => (
+                Shape::Cube,
+                (
+                    f32::from_bits(half_extents[0]),
+                    f32::from_bits(half_extents[1]),
+                    f32::from_bits(half_extents[2]),
+                ),
+            ),
+            MeshKey::Sphere { radius } => {
+                let radius = f32::from_bits(radius);
+                (Shape::Sphere(16, 16), (radius, radius, radius))
+            }
+            MeshKey::Capsule {
+                half_height,
+                radius,
+            } => {
+                return capsule_mesh_data(f32::from_bits(half_height), f32::from_bits(radius));
+            }
+            MeshKey::Cylinder {
+                half_height,
+                radius,
+            } => {
+                return cylinder_mesh_data(f32::from_bits(half_height), f32::from_bits(radius));
+            }
+        };
+
+        shape
+            .generate::<(Vec<Position>, Vec<Normal>, Vec<Tangent>, Vec<TexCoord>)>(Some(scale))
+            .into()
+    }
+}
+
+/// Capsule along the Y axis, as the physics one.
+fn capsule_mesh_data(half_height: f32, radius: f32) -> types::MeshData {
+    let mut profile = Vec::with_capacity((CAPSULE_RINGS + 1) * 2);
+    for i in 0..=CAPSULE_RINGS {
+        let angle = (i as f32 / CAPSULE_RINGS as f32 - 1.0) * std::f32::consts::FRAC_PI_2;
+        profile.push(ProfilePoint::on_sphere(radius, -half_height, angle));
+    }
+    for i in 0..=CAPSULE_RINGS {
+        let angle = i as f32 / CAPSULE_RINGS as f32 * std::f32::consts::FRAC_PI_2;
+        profile.push(ProfilePoint::on_sphere(radius, half_height, angle));
+    }
+    revolve_mesh_data(&profile)
+}
+
+/// Cylinder along the Y axis, as the physics one.
+fn cylinder_mesh_data(half_height: f32, radius: f32) -> types::MeshData {
+    let down = (0.0, -1.0);
+    let side = (1.0, 0.0);
+    let up = (0.0, 1.0);
+    revolve_mesh_data(&[
+        ProfilePoint::new(0.0, -half_height, down),
+        ProfilePoint::new(radius, -half_height, down),
+        ProfilePoint::new(radius, -half_height, side),
+        ProfilePoint::new(radius, half_height, side),
+        ProfilePoint::new(radius, half_height, up),
+        ProfilePoint::new(0.0, half_height, up),
+    ])
+}
+
+/// Point of the profile revolved around the Y axis.
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    /// Normal, as radial and vertical components.
+    normal: (f32, f32),
+}
+
+impl ProfilePoint {
+    fn new(radius: f32, y: f32, normal: (f32, f32)) -> Self {
+        ProfilePoint { radius, y, normal }
+    }
+
+    /// Point of the sphere centered at `center_y`, at this elevation angle.
+    fn on_sphere(radius: f32, center_y: f32, angle: f32) -> Self {
+        ProfilePoint {
+            radius: radius * angle.cos(),
+            y: center_y + radius * angle.sin(),
+            normal: (angle.cos(), angle.sin()),
+        }
+    }
+}
+
+/// Builds a mesh revolving the profile, ordered from bottom to top, around
+/// the Y axis.
+fn revolve_mesh_data(profile: &[ProfilePoint]) -> types::MeshData {
+    let vertex_count = profile.len() * (REVOLVE_SEGMENTS + 1);
+    let mut positions = Vec::with_capacity(vertex_count);
+    let mut normals = Vec::with_capacity(vertex_count);
+    let mut tangents = Vec::with_capacity(vertex_count);
+    let mut tex_coords = Vec::with_capacity(vertex_count);
+
+    for (ring, point) in profile.iter().enumerate() {
+        for segment in 0..=REVOLVE_SEGMENTS {
+            let u = segment as f32 / REVOLVE_SEGMENTS as f32;
+            let (sin, cos) = (u * std::f32::consts::PI * 2.0).sin_cos();
+
+            positions.push(Position([point.radius * cos, point.y, point.radius * sin]));
+            normals.push(Normal([
+                point.normal.0 * cos,
+                point.normal.1,
+                point.normal.0 * sin,
+            ]));
+            tangents.push(Tangent([-sin, 0.0, cos, 1.0]));
+            tex_coords.push(TexCoord([u, ring as f32 / (profile.len() - 1) as f32]));
+        }
+    }
+
+    let stride = (REVOLVE_SEGMENTS + 1) as u32;
+    let mut indices = Vec::with_capacity((profile.len() - 1) * REVOLVE_SEGMENTS * 6);
+    for ring in 0..(profile.len() - 1) as u32 {
+        for segment in 0..REVOLVE_SEGMENTS as u32 {
+            let a = ring * stride + segment;
+            let b = a + stride;
+            indices.extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+        }
+    }
+
+    MeshBuilder::new()
+        .with_indices(indices)
+        .with_vertices(positions)
+        .with_vertices(normals)
+        .with_vertices(tangents)
+        .with_vertices(tex_coords)
+        .into()
+}
+
+/// Identifies a material by color, metallic and roughness stored as bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialKey([u32; 6]);
+
+impl MaterialKey {
+    pub fn new(color: LinSrgba, metallic: f32, roughness: f32) -> Self {
+        MaterialKey([
+            color.red.to_bits(),
+            color.green.to_bits(),
+            color.blue.to_bits(),
+            color.alpha.to_bits(),
+            metallic.to_bits(),
+            roughness.to_bits(),
+        ])
+    }
+}
+
+/// Cache of the generated visual assets, so identical objects share the same
+/// handles instead of uploading a copy each.
+#[derive(Default)]
+pub struct VisualAssetCache {
+    meshes: HashMap<MeshKey, Handle<types::Mesh>>,
+    materials: HashMap<MaterialKey, Handle<mtl::Material>>,
+}
+
+/// Returns the cached mesh for this key, generating it the first time.
+pub fn shared_mesh(world: &mut World, key: MeshKey) -> Handle<types::Mesh> {
+    let cached = world
+        .entry::<VisualAssetCache>()
+        .or_insert_with(Default::default)
+        .meshes
+        .get(&key)
+        .cloned();
+
+    cached.unwrap_or_else(|| {
+        let mesh = create_mesh(world, key.generate());
+        world
+            .fetch_mut::<VisualAssetCache>()
+            .meshes
+            .insert(key, mesh.clone());
+        mesh
+    })
+}
+
+/// Returns the cached material with these parameters, creating it the first time.
+///
+/// The cached materials are never freed, so it's meant for a small set of
+/// colors, not for a random color per object.
+pub fn shared_material(
+    world: &mut World,
+    color: LinSrgba,
+    metallic: f32,
+    roughness: f32,
+) -> Handle<mtl::Material> {
+    let key = MaterialKey::new(color, metallic, roughness);
+    let cached = world
+        .entry::<VisualAssetCache>()
+        .or_insert_with(Default::default)
+        .materials
+        .get(&key)
+        .cloned();
+
+    cached.unwrap_or_else(|| {
+        let mat = create_material(world, color, metallic, roughness);
+        world
+            .fetch_mut::<VisualAssetCache>()
+            .materials
+            .insert(key, mat.clone());
+        mat
+    })
+}
+
+/// Returns a mesh matching the collider, so render and collision can't drift
+/// apart.
+///
+/// The analytic shapes are taken from the cache, while the convex and triangle
+/// meshes get a new mesh. The compound shapes return `None`, since each part
+/// needs its own entity.
+pub fn mesh_from_shape_desc(
+    world: &mut World,
+    desc: &ShapeDesc<f32>,
+) -> Option<Handle<types::Mesh>> {
+    if let Some(key) = MeshKey::from_shape_desc(desc) {
+        return Some(shared_mesh(world, key));
+    }
+
+    match desc {
+        ShapeDesc::Convex { points } => {
+            let hull = transformation::convex_hull(points);
+            let indices: Vec<Point3<usize>> = match &hull.indices {
+                IndexBuffer::Unified(indices) => indices
+                    .iter()
+                    .map(|t| Point3::new(t.x as usize, t.y as usize, t.z as usize))
+                    .collect(),
+                IndexBuffer::Split(indices) => indices
+                    .iter()
+                    .map(|t| Point3::new(t.x.x as usize, t.y.x as usize, t.z.x as usize))
+                    .collect(),
+            };
+            Some(create_mesh(
+                world,
+                mesh_data_from_triangles(&hull.coords, &indices),
+            ))
+        }
+        ShapeDesc::TriMesh { points, indices } => Some(create_mesh(
+            world,
+            mesh_data_from_triangles(points, indices),
+        )),
+        _ => None,
+    }
+}
+
+/// Radius of the sphere, centered on the shape origin, that contains the shape.
+pub fn bounding_radius(desc: &ShapeDesc<f32>) -> f32 {
+    match desc {
+        ShapeDesc::Cube { half_extents } => half_extents.norm(),
+        ShapeDesc::Sphere { radius } => *radius,
+        ShapeDesc::Capsule {
+            half_height,
+            radius,
+        } => half_height + radius,
+        ShapeDesc::Cylinder {
+            half_height,
+            radius,
+        } => (half_height * half_height + radius * radius).sqrt(),
+        ShapeDesc::Convex { points } | ShapeDesc::TriMesh { points, .. } => {
+            points.iter().map(|p| p.coords.norm()).fold(0.0, f32::max)
+        }
+        ShapeDesc::Compound { shapes } => shapes
+            .iter()
+            .map(|(offset, shape)| offset.translation.vector.norm() + bounding_radius(shape))
+            .fold(0.0, f32::max),
+        _ => 1.0,
+    }
+}
 
 pub fn create_mesh(world: &World, mesh_data: types::MeshData) -> Handle<types::Mesh> {
     // Mesh creation
@@ -12,6 +354,46 @@ pub fn create_mesh(world: &World, mesh_data: types::MeshData) -> Handle<types::M
     loader.load_from_data(mesh_data, (), &asset_storage)
 }
 
+/// Builds a flat shaded mesh from a list of triangles.
+pub fn mesh_data_from_triangles(
+    points: &[Point3<f32>],
+    indices: &[Point3<usize>],
+) -> types::MeshData {
+    let vertex_count = indices.len() * 3;
+    let mut positions = Vec::with_capacity(vertex_count);
+    let mut normals = Vec::with_capacity(vertex_count);
+    let mut tangents = Vec::with_capacity(vertex_count);
+    let mut tex_coords = Vec::with_capacity(vertex_count);
+
+    for triangle in indices {
+        let a = points[triangle.x];
+        let b = points[triangle.y];
+        let c = points[triangle.z];
+
+        let normal = (b - a)
+            .cross(&(c - a))
+            .try_normalize(std::f32::EPSILON)
+            .unwrap_or_else(Vector3::y);
+        let tangent = (b - a)
+            .try_normalize(std::f32::EPSILON)
+            .unwrap_or_else(Vector3::x);
+
+        for p in &[a, b, c] {
+            positions.push(Position([p.x, p.y, p.z]));
+            normals.push(Normal([normal.x, normal.y, normal.z]));
+            tangents.push(Tangent([tangent.x, tangent.y, tangent.z, 1.0]));
+            tex_coords.push(TexCoord([0.0, 0.0]));
+        }
+    }
+
+    MeshBuilder::new()
+        .with_vertices(positions)
+        .with_vertices(normals)
+        .with_vertices(tangents)
+        .with_vertices(tex_coords)
+        .into()
+}
+
 pub fn create_material(
     world: &World,
     color: LinSrgba,